@@ -0,0 +1,135 @@
+//! Looks mangas up on `AniList`, whose titles are searched for on mangadex
+//! when only the manga's `AniList` or `MyAnimeList` id is known.
+
+use crate::{api::Api, identifier::Site};
+use anyhow::{bail, Context, Result};
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Fetches a manga by either of its ids, only one of them is given.
+const QUERY: &str = "query ($id: Int, $idMal: Int) {
+  Media(id: $id, idMal: $idMal, type: MANGA) {
+    title { english romaji native }
+    synonyms
+  }
+}";
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    data: Option<Data>,
+    #[serde(default)]
+    errors: Vec<Error>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Data {
+    #[serde(rename = "Media")]
+    media: Option<Media>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Media {
+    title: Titles,
+    #[serde(default)]
+    synonyms: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Titles {
+    english: Option<String>,
+    romaji: Option<String>,
+    native: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Error {
+    message: String,
+}
+
+impl Media {
+    /// The English, romaji and native titles followed by the synonyms,
+    /// without repeats.
+    fn titles(self) -> Vec<String> {
+        let mut titles: Vec<String> = Vec::new();
+        let all = [self.title.english, self.title.romaji, self.title.native]
+            .into_iter()
+            .flatten()
+            .chain(self.synonyms);
+        for title in all {
+            if !title.is_empty() && !titles.contains(&title) {
+                titles.push(title);
+            }
+        }
+        titles
+    }
+}
+
+/// The titles `AniList` knows the manga with the given `AniList` or
+/// `MyAnimeList` id by.
+///
+/// # Errors
+/// Fails if the site has no `AniList` lookup, the id is not a number, or
+/// `AniList` cannot be reached or does not know the manga.
+pub(crate) async fn titles(api: &Api, site: Site, id: &str) -> Result<Vec<String>> {
+    let number: u32 = id
+        .parse()
+        .with_context(|| format!("The {} id {:?} is not a number", site, id))?;
+    let variables = match site {
+        Site::Anilist => json!({ "id": number }),
+        Site::MyAnimeList => json!({ "idMal": number }),
+        Site::Kitsu | Site::MangaUpdates => bail!("AniList cannot look up {} ids", site),
+    };
+    let body = serde_json::to_vec(&json!({ "query": QUERY, "variables": variables }))?;
+    // AniList answers unknown ids with an error status and the reason in
+    // `errors`, so the status is not checked
+    let bytes = api
+        .http
+        .post(api.anilist_url.clone())
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await?
+        .bytes()
+        .await?;
+    let response: Response =
+        serde_json::from_slice(&bytes).context("Unable to parse the AniList response")?;
+
+    if let Some(error) = response.errors.first() {
+        bail!("AniList could not find {}:{}: {}", site, id, error.message);
+    }
+    let titles = response
+        .data
+        .and_then(|data| data.media)
+        .with_context(|| format!("AniList does not know {}:{}", site, id))?
+        .titles();
+    if titles.is_empty() {
+        bail!("AniList has no titles for {}:{}", site, id);
+    }
+    Ok(titles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_skip_repeats() {
+        let media = Media {
+            title: Titles {
+                english: Some("Komi Can't Communicate".to_string()),
+                romaji: Some("Komi-san wa, Comyushou desu.".to_string()),
+                native: None,
+            },
+            synonyms: vec!["Komi Can't Communicate".to_string(), "Komi-san".to_string()],
+        };
+        assert_eq!(
+            media.titles(),
+            vec![
+                "Komi Can't Communicate",
+                "Komi-san wa, Comyushou desu.",
+                "Komi-san"
+            ]
+        );
+    }
+}
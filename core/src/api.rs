@@ -0,0 +1,36 @@
+use anyhow::Result;
+use mangadex_api::MangaDexClient;
+use url::Url;
+use uuid::Uuid;
+
+pub const API_URL: &str = "https://api.mangadex.org";
+pub const UPLOADS_URL: &str = "https://uploads.mangadex.org";
+pub const ANILIST_URL: &str = "https://graphql.anilist.co";
+
+/// The clients and urls used to talk to mangadex, shared by everything
+/// that makes requests.
+#[derive(Debug, Clone)]
+pub struct Api {
+    pub client: MangaDexClient,
+    /// Used for requests the mangadex client does not make itself
+    pub http: reqwest::Client,
+    pub api_url: Url,
+    pub uploads_url: Url,
+    /// Where mangas only known by their `AniList` or `MyAnimeList` id are
+    /// looked up
+    pub anilist_url: Url,
+    /// Whether to report page download results to the mangadex network
+    pub report: bool,
+}
+
+impl Api {
+    /// Where the cover file of a manga is served.
+    ///
+    /// # Errors
+    /// Fails if the file name does not make a valid url.
+    pub fn cover_url(&self, manga_id: &Uuid, file_name: &str) -> Result<Url> {
+        Ok(self
+            .uploads_url
+            .join(&format!("covers/{}/{}", manga_id, file_name))?)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{cassette::Interaction, files::write_atomic, gateway::ANILIST_PATH};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Api endpoints whose responses are cached. At-home server urls expire
+/// quickly so they are always fetched.
+const CACHED_PATHS: [&str; 3] = ["/manga", "/cover", "/author"];
+
+/// An on-disk cache of mangadex api responses and `AniList` lookups, so
+/// fetching the same manga again does not repeat every request. Responses younger than the TTL are
+/// used as is, older ones are revalidated with their `ETag` or
+/// `Last-Modified` header when mangadex sent one. Chapter feeds change
+/// whenever a chapter is released, so they are always revalidated.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+/// The headers used to ask mangadex whether a stale response changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    /// The full url of the request, including the api it was sent to, and
+    /// the body of `AniList` lookups
+    pub url: String,
+    /// Seconds since the unix epoch the response was fetched or revalidated
+    pub stored: u64,
+    pub validators: Validators,
+    pub response: Interaction,
+}
+
+impl Entry {
+    pub(crate) fn new(url: String, response: Interaction, validators: Validators) -> Self {
+        Self {
+            url,
+            stored: now(),
+            validators,
+            response,
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// A stable FNV-1a hash, the file names must not change between builds.
+fn hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Whether `uri` is the chapter feed of a manga, `/manga/{id}/feed`.
+fn is_feed(uri: &str) -> bool {
+    let path = uri.split_once('?').map_or(uri, |(path, _)| path);
+    path.starts_with("/manga/") && path.ends_with("/feed")
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    /// Removes every cached response.
+    ///
+    /// # Errors
+    /// Fails if the cache directory cannot be removed.
+    pub fn clear(&self) -> Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)
+                .with_context(|| format!("Unable to clear cache {}", self.dir.display()))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn caches(method: &str, uri: &str) -> bool {
+        match method {
+            "GET" => CACHED_PATHS.iter().any(|path| uri.starts_with(path)),
+            "POST" => uri.starts_with(ANILIST_PATH),
+            _ => false,
+        }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", hash(url)))
+    }
+
+    /// The cached response for `url`, however old it is. Unreadable entries
+    /// are treated as missing.
+    pub(crate) fn get(&self, url: &str) -> Option<Entry> {
+        let entry: Entry = serde_json::from_slice(&fs::read(self.path(url)).ok()?).ok()?;
+        // Guard against hash collisions
+        (entry.url == url).then_some(entry)
+    }
+
+    /// Whether the entry can be used without asking mangadex.
+    pub(crate) fn is_fresh(&self, entry: &Entry) -> bool {
+        !is_feed(&entry.response.uri) && now().saturating_sub(entry.stored) < self.ttl.as_secs()
+    }
+
+    /// # Errors
+    /// Fails if the entry cannot be written.
+    pub(crate) fn put(&self, entry: &Entry) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.path(&entry.url), &serde_json::to_vec(entry)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_metadata_only() {
+        assert!(Cache::caches("GET", "/manga/a96676e5/feed?offset=0"));
+        assert!(Cache::caches("GET", "/cover?manga[]=a96676e5"));
+        assert!(!Cache::caches("GET", "/at-home/server/a96676e5"));
+        assert!(!Cache::caches("POST", "/manga"));
+        assert!(Cache::caches("POST", "/anilist"));
+    }
+
+    #[test]
+    fn feeds_are_always_revalidated() {
+        let cache = Cache::new("/cache", Duration::from_secs(3600));
+        let entry = |uri: &str| {
+            Entry::new(
+                format!("https://api.mangadex.org{}", uri),
+                Interaction {
+                    method: "GET".into(),
+                    uri: uri.into(),
+                    request: None,
+                    status: 200,
+                    content_type: None,
+                    body: String::new(),
+                },
+                Validators::default(),
+            )
+        };
+        assert!(cache.is_fresh(&entry("/manga/a96676e5")));
+        assert!(!cache.is_fresh(&entry("/manga/a96676e5/feed?offset=0")));
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::files::write_atomic;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Whether api requests are recorded into or replayed from a cassette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Forward every request to mangadex and save it with its response
+    Record(PathBuf),
+    /// Answer every request from the cassette without touching the network
+    Replay(PathBuf),
+}
+
+/// The api requests made during a session and the responses they got, so
+/// the session can be replayed offline to reproduce a bug.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    /// The path and query of the request
+    pub uri: String,
+    /// The body of the request, only `AniList` lookups have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+impl Cassette {
+    /// # Errors
+    /// Fails if the cassette cannot be read or parsed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read(path)
+            .with_context(|| format!("Unable to read cassette {}", path.display()))?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("Unable to parse cassette {}", path.display()))
+    }
+
+    /// # Errors
+    /// Fails if the cassette cannot be written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// A request as told apart by the cassette: its method, uri and body.
+type Key = (String, String, Option<String>);
+
+/// Serves the interactions of a cassette. Requests made several times are
+/// answered in the order they were recorded, repeating the last answer
+/// once they run out.
+#[derive(Debug)]
+pub(crate) struct Player {
+    interactions: HashMap<Key, Vec<Interaction>>,
+    played: HashMap<Key, usize>,
+}
+
+impl Player {
+    pub(crate) fn new(cassette: Cassette) -> Self {
+        let mut interactions: HashMap<Key, Vec<Interaction>> = HashMap::new();
+        for interaction in cassette.interactions {
+            interactions
+                .entry((
+                    interaction.method.clone(),
+                    interaction.uri.clone(),
+                    interaction.request.clone(),
+                ))
+                .or_default()
+                .push(interaction);
+        }
+        Self {
+            interactions,
+            played: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn play(
+        &mut self,
+        method: &str,
+        uri: &str,
+        request: Option<&str>,
+    ) -> Option<Interaction> {
+        let key = (
+            method.to_string(),
+            uri.to_string(),
+            request.map(ToString::to_string),
+        );
+        let recorded = self.interactions.get(&key)?;
+        let played = self.played.entry(key).or_insert(0);
+        let interaction = recorded.get(*played).or_else(|| recorded.last()).cloned();
+        *played += 1;
+        interaction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(uri: &str, body: &str) -> Interaction {
+        Interaction {
+            method: "GET".into(),
+            uri: uri.into(),
+            request: None,
+            status: 200,
+            content_type: Some("application/json".into()),
+            body: body.into(),
+        }
+    }
+
+    #[test]
+    fn replays_in_recorded_order() {
+        let mut player = Player::new(Cassette {
+            interactions: vec![
+                interaction("/manga?title=komi", "first"),
+                interaction("/cover", "cover"),
+                interaction("/manga?title=komi", "second"),
+            ],
+        });
+
+        let mut play = |uri| {
+            player
+                .play("GET", uri, None)
+                .map(|interaction| interaction.body)
+        };
+        assert_eq!(play("/manga?title=komi").as_deref(), Some("first"));
+        assert_eq!(play("/manga?title=komi").as_deref(), Some("second"));
+        assert_eq!(play("/manga?title=komi").as_deref(), Some("second"));
+        assert_eq!(play("/cover").as_deref(), Some("cover"));
+        assert_eq!(play("/manga?title=other"), None);
+    }
+
+    #[test]
+    fn requests_are_told_apart_by_body() {
+        let lookup = |request: &str, body: &str| Interaction {
+            method: "POST".into(),
+            request: Some(request.into()),
+            ..interaction("/anilist", body)
+        };
+        let mut player = Player::new(Cassette {
+            interactions: vec![lookup("{\"id\":1}", "one"), lookup("{\"id\":2}", "two")],
+        });
+
+        let mut play = |request| {
+            player
+                .play("POST", "/anilist", Some(request))
+                .map(|interaction| interaction.body)
+        };
+        assert_eq!(play("{\"id\":2}").as_deref(), Some("two"));
+        assert_eq!(play("{\"id\":1}").as_deref(), Some("one"));
+        assert_eq!(play("{\"id\":3}"), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Finding the credit and recruitment pages scanlation groups put at the
+//! start or end of their chapters. Pages are compared by a perceptual hash,
+//! so a group's credit page is found again even after being re-encoded.
+
+use crate::package::chapter_pages;
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Pages at each end of a chapter that may be credits.
+const EDGE_PAGES: usize = 2;
+/// Shrunk pages whose shades span less than this are blank, and blank
+/// pages all hash alike.
+const BLANK_RANGE: u8 = 8;
+
+/// The difference hash of a page: 64 bits, one for each pair of
+/// neighbouring pixels of the page shrunk to 9x8, set where the left one is
+/// darker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PageHash(pub u64);
+
+/// Skips (and optionally deletes) credit pages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CreditFilter {
+    /// Hashes of pages that are always credits
+    pub blocklist: Vec<PageHash>,
+    /// How many bits two hashes may differ in and still be the same page
+    pub max_distance: u32,
+    /// Delete credit pages from disk instead of only leaving them out of
+    /// packages
+    pub delete: bool,
+}
+
+impl PageHash {
+    /// The hash of the page at `path`, `None` if it is blank.
+    ///
+    /// # Errors
+    /// Fails if the page cannot be decoded.
+    pub fn of(path: &Path) -> Result<Option<Self>> {
+        let image =
+            image::open(path).with_context(|| format!("Unable to read page {}", path.display()))?;
+        let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+        let darkest = small.pixels().map(|pixel| pixel.0[0]).min().unwrap_or(0);
+        let lightest = small.pixels().map(|pixel| pixel.0[0]).max().unwrap_or(0);
+        if lightest - darkest < BLANK_RANGE {
+            return Ok(None);
+        }
+        let mut hash = 0;
+        for y in 0..8 {
+            for x in 0..8 {
+                let darker = small.get_pixel(x, y).0[0] < small.get_pixel(x + 1, y).0[0];
+                hash = hash << 1 | u64::from(darker);
+            }
+        }
+        Ok(Some(Self(hash)))
+    }
+
+    pub fn distance(self, other: Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl CreditFilter {
+    /// The default distance, a few bits for noise from re-encoding.
+    pub const MAX_DISTANCE: u32 = 6;
+
+    pub fn new(blocklist: Vec<PageHash>, delete: bool) -> Self {
+        Self {
+            blocklist,
+            max_distance: Self::MAX_DISTANCE,
+            delete,
+        }
+    }
+
+    fn matches(&self, hash: PageHash, hashes: &[PageHash]) -> bool {
+        hashes
+            .iter()
+            .any(|other| hash.distance(*other) <= self.max_distance)
+    }
+
+    /// The credit pages among the first and last pages of `chapters`, each
+    /// given with the groups that released it. A page is a credit when it is
+    /// on the blocklist, matches a credit already `seen` from one of its
+    /// groups, or matches an end page of another chapter by the same group.
+    /// The hashes of the credits found are added to `seen`.
+    ///
+    /// # Errors
+    /// Fails if a chapter folder cannot be read. Blank pages and pages that
+    /// cannot be decoded are never credits.
+    pub(crate) fn find(
+        &self,
+        chapters: &[(&[String], &Path)],
+        seen: &mut BTreeMap<String, Vec<PageHash>>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut candidates: Vec<(usize, PathBuf, PageHash)> = Vec::new();
+        for (index, (_, dir)) in chapters.iter().enumerate() {
+            let pages = chapter_pages(dir)?;
+            let ends = pages.len().saturating_sub(EDGE_PAGES).max(EDGE_PAGES);
+            for (position, page) in pages.into_iter().enumerate() {
+                if position < EDGE_PAGES || position >= ends {
+                    if let Ok(Some(hash)) = PageHash::of(&page) {
+                        candidates.push((index, page, hash));
+                    }
+                }
+            }
+        }
+
+        let mut credits = Vec::new();
+        for (index, page, hash) in &candidates {
+            let groups = chapters[*index].0;
+            let known = groups
+                .iter()
+                .filter_map(|group| seen.get(group))
+                .any(|hashes| self.matches(*hash, hashes));
+            let repeated = candidates.iter().any(|(other, _, other_hash)| {
+                other != index
+                    && chapters[*other]
+                        .0
+                        .iter()
+                        .any(|group| groups.contains(group))
+                    && hash.distance(*other_hash) <= self.max_distance
+            });
+            if self.matches(*hash, &self.blocklist) || known || repeated {
+                credits.push((groups, page.clone(), *hash));
+            }
+        }
+
+        for (groups, _, hash) in &credits {
+            for group in groups.iter() {
+                let hashes = seen.entry(group.clone()).or_default();
+                if !self.matches(*hash, hashes) {
+                    hashes.push(*hash);
+                }
+            }
+        }
+        Ok(credits.into_iter().map(|(_, page, _)| page).collect())
+    }
+}
+
+impl Display for PageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for PageHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        u64::from_str_radix(s, 16)
+            .map(Self)
+            .with_context(|| format!("{:?} is not a page hash", s))
+    }
+}
+
+impl TryFrom<String> for PageHash {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<PageHash> for String {
+    fn from(value: PageHash) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    /// Saves a page of noise from `seed`, or the same gradient for every
+    /// credit page.
+    fn page(path: &Path, seed: Option<u32>) {
+        let mut state = seed.unwrap_or(0).wrapping_mul(2_654_435_761) | 1;
+        let page = GrayImage::from_fn(90, 80, |x, y| match seed {
+            Some(_) => {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                Luma([(state >> 24) as u8])
+            }
+            None => Luma([(x * 2 + y) as u8]),
+        });
+        page.save(path).unwrap();
+    }
+
+    /// A chapter of five pages, the last one a credit page.
+    fn chapter(root: &Path, number: u32) -> PathBuf {
+        let dir = root.join(number.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        for index in 1..=5 {
+            let seed = (index < 5).then_some(number * 10 + index);
+            page(&dir.join(format!("{}.png", index)), seed);
+        }
+        dir
+    }
+
+    #[test]
+    fn hashes_round_trip() {
+        let hash = PageHash(0x00ff_00ff_1234_abcd);
+        assert_eq!(hash.to_string(), "00ff00ff1234abcd");
+        assert_eq!(hash.to_string().parse::<PageHash>().unwrap(), hash);
+        assert_eq!(hash.distance(PageHash(0x00ff_00ff_1234_abcc)), 1);
+    }
+
+    #[test]
+    fn finds_credits_repeated_by_a_group() {
+        let root = tempfile::tempdir().unwrap();
+        let one = chapter(root.path(), 1);
+        let two = chapter(root.path(), 2);
+        let other = chapter(root.path(), 3);
+        let group = ["Group".to_string()];
+        let different = ["Other".to_string()];
+
+        let mut seen = BTreeMap::new();
+        let filter = CreditFilter::new(Vec::new(), false);
+        // The same page from two groups is not a credit
+        let groups = [
+            (&group[..], one.as_path()),
+            (&different[..], other.as_path()),
+        ];
+        assert!(filter.find(&groups, &mut seen).unwrap().is_empty());
+
+        let chapters = [(&group[..], one.as_path()), (&group[..], two.as_path())];
+        let credits = filter.find(&chapters, &mut seen).unwrap();
+        assert_eq!(credits, vec![one.join("5.png"), two.join("5.png")]);
+        assert!(seen.contains_key("Group"));
+
+        // A later chapter on its own is matched against the seen credits
+        let later = filter
+            .find(&[(&group[..], other.as_path())], &mut seen)
+            .unwrap();
+        assert_eq!(later, vec![other.join("5.png")]);
+    }
+
+    #[test]
+    fn blocklisted_pages_are_credits() {
+        let root = tempfile::tempdir().unwrap();
+        let one = chapter(root.path(), 1);
+        let hash = PageHash::of(&one.join("5.png")).unwrap().unwrap();
+        let filter = CreditFilter::new(vec![hash], true);
+        let group = ["Group".to_string()];
+        let credits = filter
+            .find(&[(&group[..], one.as_path())], &mut BTreeMap::new())
+            .unwrap();
+        assert_eq!(credits, vec![one.join("5.png")]);
+    }
+
+    #[test]
+    fn blank_pages_have_no_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blank.png");
+        GrayImage::from_pixel(40, 60, Luma([250]))
+            .save(&path)
+            .unwrap();
+        assert_eq!(PageHash::of(&path).unwrap(), None);
+    }
+}
@@ -0,0 +1,847 @@
+use crate::{
+    anilist,
+    api::{Api, ANILIST_URL, API_URL, UPLOADS_URL},
+    credits::CreditFilter,
+    files::file_name,
+    identifier::Site,
+    info::SearchResult,
+    manga::{
+        chapter_folder, volume_folder, Chapter, Cover, CoverLocales, CoverPolicy, Group, Manga,
+        MangaData, ReadingDirection, Volume, VolumeGrouping,
+    },
+    process::{Processing, Webtoon},
+};
+#[cfg(feature = "gateway")]
+use crate::{
+    cache::Cache,
+    cassette::CassetteMode,
+    gateway,
+    progress::{NoProgress, ProgressSink},
+};
+use anyhow::{bail, Context, Result};
+use mangadex_api::{HttpClient, MangaDexClient};
+use mangadex_api_types_rust::{
+    Language, MangaFeedSortOrder, OrderDirection, ReferenceExpansionResource, RelationshipType,
+};
+#[cfg(feature = "gateway")]
+use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+use url::Url;
+use uuid::Uuid;
+
+/// Entry point of the library, holds the mangadex client shared by every
+/// request.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    api: Api,
+}
+
+#[derive(Default)]
+pub struct DownloaderBuilder {
+    anilist_url: Option<Url>,
+    api_url: Option<Url>,
+    #[cfg(feature = "gateway")]
+    cache: Option<Cache>,
+    #[cfg(feature = "gateway")]
+    cassette: Option<CassetteMode>,
+    #[cfg(feature = "gateway")]
+    progress: Option<Arc<dyn ProgressSink>>,
+    uploads_url: Option<Url>,
+    report: Option<bool>,
+    timeout: Option<Duration>,
+}
+
+// Sinks have no `Debug`, so the progress is left out
+impl fmt::Debug for DownloaderBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut builder = f.debug_struct("DownloaderBuilder");
+        builder
+            .field("anilist_url", &self.anilist_url)
+            .field("api_url", &self.api_url);
+        #[cfg(feature = "gateway")]
+        builder
+            .field("cache", &self.cache)
+            .field("cassette", &self.cassette);
+        builder
+            .field("uploads_url", &self.uploads_url)
+            .field("report", &self.report)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// How to find the mangadex UUID of a manga.
+#[derive(Debug, Clone)]
+pub enum SeriesQuery {
+    Id(Uuid),
+    /// The manga a chapter belongs to
+    Chapter(Uuid),
+    /// Searches mangadex for the title and uses the result that links to
+    /// `id` on `site`. Mangadex cannot be asked for links directly, so
+    /// without a title (or when it finds nothing) the titles `AniList` knows
+    /// the manga by are searched for, which only works for `AniList` and
+    /// `MyAnimeList` ids.
+    Link {
+        site: Site,
+        id: String,
+        title: Option<String>,
+        translated_language: Language,
+    },
+    /// Searches mangadex for the title. If `anilist_id` is set the result
+    /// linking to that anilist entry is used, searching for the titles
+    /// `AniList` knows it by if the title finds none. Otherwise the first
+    /// result is used.
+    Title {
+        title: String,
+        anilist_id: Option<u32>,
+        translated_language: Language,
+    },
+}
+
+/// Options for fetching one manga, created by `Downloader::series`.
+#[derive(Debug)]
+pub struct SeriesBuilder<'a> {
+    downloader: &'a Downloader,
+    id: Uuid,
+    all_volume_covers: bool,
+    chapter: Option<Uuid>,
+    cover_locales: CoverLocales,
+    cover_policy: CoverPolicy,
+    cover_thumbnails: bool,
+    credits: Option<CreditFilter>,
+    download_covers: bool,
+    output: PathBuf,
+    package_chapters: bool,
+    preferred_groups: Vec<String>,
+    processing: Processing,
+    reading_direction: Option<ReadingDirection>,
+    title: Option<String>,
+    translated_language: Language,
+    volume_grouping: VolumeGrouping,
+}
+
+impl DownloaderBuilder {
+    /// The `AniList` `GraphQL` api used to look up the titles of mangas only
+    /// known by their `AniList` or `MyAnimeList` id, defaults to
+    /// `https://graphql.anilist.co`.
+    pub fn anilist_url(mut self, url: Url) -> Self {
+        self.anilist_url = Some(url);
+        self
+    }
+
+    /// The base url of the mangadex api, defaults to `https://api.mangadex.org`.
+    pub fn api_url(mut self, url: Url) -> Self {
+        self.api_url = Some(url);
+        self
+    }
+
+    /// Cache api responses on disk, there is no cache by default.
+    #[cfg(feature = "gateway")]
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Record every api request and its response into a cassette, or
+    /// answer them from one without going online. Images are not part of
+    /// the cassette.
+    #[cfg(feature = "gateway")]
+    pub fn cassette(mut self, cassette: CassetteMode) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// Where responses the cache could not save are reported, as
+    /// `ProgressEvent::CacheFailed`. They are ignored by default.
+    #[cfg(feature = "gateway")]
+    pub fn progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// The base url covers are downloaded from, defaults to
+    /// `https://uploads.mangadex.org`.
+    pub fn uploads_url(mut self, url: Url) -> Self {
+        self.uploads_url = Some(url);
+        self
+    }
+
+    /// Whether to report page downloads to the mangadex network, as asked
+    /// of at-home clients. Defaults to true unless a custom `api_url` is
+    /// used.
+    pub fn report(mut self, report: bool) -> Self {
+        self.report = Some(report);
+        self
+    }
+
+    /// Timeout for every request, there is none by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// # Errors
+    /// Fails if the http client cannot be created or the cassette cannot
+    /// be loaded. A cassette or cache also fails outside a tokio runtime.
+    pub fn build(self) -> Result<Downloader> {
+        let mut http = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+        let http = http.build()?;
+
+        let api_url = match self.api_url.clone() {
+            Some(url) => url,
+            None => Url::parse(API_URL)?,
+        };
+        let anilist_url = match self.anilist_url {
+            Some(url) => url,
+            None => Url::parse(ANILIST_URL)?,
+        };
+        // With a cassette or cache the client and AniList lookups talk to
+        // our gateway instead
+        #[cfg(feature = "gateway")]
+        let (base_url, anilist_url) = if self.cassette.is_some() || self.cache.is_some() {
+            let gateway = gateway::start(
+                self.cassette.as_ref(),
+                self.cache.clone(),
+                api_url.clone(),
+                anilist_url,
+                http.clone(),
+                self.progress.unwrap_or_else(|| Arc::new(NoProgress)),
+            )?;
+            let lookups = gateway.join(gateway::ANILIST_PATH)?;
+            (gateway, lookups)
+        } else {
+            (api_url.clone(), anilist_url)
+        };
+        #[cfg(not(feature = "gateway"))]
+        let (base_url, anilist_url) = (api_url.clone(), anilist_url);
+        let client = MangaDexClient::new_with_http_client(
+            HttpClient::builder()
+                .client(http.clone())
+                .base_url(base_url)
+                .build()?,
+        );
+
+        Ok(Downloader {
+            api: Api {
+                client,
+                http,
+                report: self.report.unwrap_or(self.api_url.is_none()),
+                api_url,
+                uploads_url: match self.uploads_url {
+                    Some(url) => url,
+                    None => Url::parse(UPLOADS_URL)?,
+                },
+                anilist_url,
+            },
+        })
+    }
+}
+
+impl Downloader {
+    pub fn builder() -> DownloaderBuilder {
+        DownloaderBuilder::default()
+    }
+
+    pub fn client(&self) -> &MangaDexClient {
+        &self.api.client
+    }
+
+    /// Searches mangadex for mangas with the title that are translated
+    /// into `translated_language`, in the order mangadex ranks them.
+    ///
+    /// # Errors
+    /// Fails if the search fails.
+    pub async fn search(
+        &self,
+        title: &str,
+        translated_language: Language,
+    ) -> Result<Vec<SearchResult>> {
+        let search_data = self
+            .api
+            .client
+            .search()
+            .manga()
+            .title(title)
+            .available_translated_language(vec![translated_language])
+            .build()?
+            .send()
+            .await?;
+
+        Ok(search_data
+            .data
+            .into_iter()
+            .map(|manga| {
+                let id = manga.id;
+                SearchResult::new(id, &MangaData::from(manga))
+            })
+            .collect())
+    }
+
+    /// The manga with the given UUID as a search result, for listing a manga
+    /// found by an id rather than by searching.
+    ///
+    /// # Errors
+    /// Fails if the manga cannot be fetched.
+    pub async fn search_result(&self, id: Uuid) -> Result<SearchResult> {
+        let manga_data = self
+            .api
+            .client
+            .manga()
+            .get()
+            .manga_id(&id)
+            .build()?
+            .send()
+            .await?;
+
+        Ok(SearchResult::new(id, &MangaData::from(manga_data.data)))
+    }
+
+    /// Finds the mangadex UUID for the query. We don't do any huristics on
+    /// our side when searching, so it is advised to search in conjunction
+    /// with an `anilist_id` value.
+    ///
+    /// # Errors
+    /// Fails if the search fails or nothing matches.
+    pub async fn resolve(&self, query: &SeriesQuery) -> Result<Uuid> {
+        let (title, link, translated_language) = match query {
+            SeriesQuery::Id(id) => return Ok(*id),
+            SeriesQuery::Chapter(id) => return self.chapter_manga(*id).await,
+            SeriesQuery::Title {
+                title,
+                anilist_id,
+                translated_language,
+            } => (
+                Some(title),
+                anilist_id.map(|id| (Site::Anilist, id.to_string())),
+                *translated_language,
+            ),
+            SeriesQuery::Link {
+                site,
+                id,
+                title,
+                translated_language,
+            } => (
+                title.as_ref(),
+                Some((*site, id.clone())),
+                *translated_language,
+            ),
+        };
+
+        let Some((site, id)) = link else {
+            let title = title.context("Missing title!")?;
+            return self
+                .search(title, translated_language)
+                .await?
+                .first()
+                .map(|manga| manga.id)
+                .with_context(|| format!("Found no manga with a title of {}", title));
+        };
+
+        if title.is_none() && !site.has_titles() {
+            bail!(
+                "{}:{} can only be found with a title to search for, mangadex cannot look mangas up by their {} id",
+                site,
+                id,
+                site
+            );
+        }
+        // The title given is searched for first, then the titles AniList
+        // knows the manga by
+        if let Some(title) = title {
+            if let Some(found) = self
+                .find_link(title, translated_language, site, &id)
+                .await?
+            {
+                return Ok(found);
+            }
+        }
+        if site.has_titles() {
+            for other in anilist::titles(&self.api, site, &id).await? {
+                if Some(&other) == title {
+                    continue;
+                }
+                if let Some(found) = self
+                    .find_link(&other, translated_language, site, &id)
+                    .await?
+                {
+                    return Ok(found);
+                }
+            }
+        }
+        bail!("No Manga Found with {} id {:?}", site, id)
+    }
+
+    /// The search result for the title that links to `id` on `site`, going
+    /// through every page of results until one is found.
+    async fn find_link(
+        &self,
+        title: &str,
+        translated_language: Language,
+        site: Site,
+        id: &str,
+    ) -> Result<Option<Uuid>> {
+        const SEARCH_LIMIT: u32 = 100; // Max that the mangadex api allows
+        const SEARCH_WINDOW: u32 = 10_000; // Mangadex pages no further
+        let mut offset = 0;
+        loop {
+            let search_data = self
+                .api
+                .client
+                .search()
+                .manga()
+                .title(title)
+                .available_translated_language(vec![translated_language])
+                .offset(offset)
+                .limit(SEARCH_LIMIT)
+                .build()?
+                .send()
+                .await?;
+
+            let found = search_data.data.into_iter().find_map(|manga| {
+                let manga_id = manga.id;
+                let metadata = MangaData::from(manga);
+                (metadata.links.get(site.link_key()).map(String::as_str) == Some(id))
+                    .then_some(manga_id)
+            });
+            if found.is_some()
+                || search_data.limit == 0
+                || search_data.limit + search_data.offset >= search_data.total.min(SEARCH_WINDOW)
+            {
+                return Ok(found);
+            }
+            offset += search_data.limit;
+        }
+    }
+
+    /// The UUID of the manga a chapter belongs to.
+    ///
+    /// # Errors
+    /// Fails if the chapter cannot be fetched.
+    pub async fn chapter_manga(&self, id: Uuid) -> Result<Uuid> {
+        let chapter_data = self
+            .api
+            .client
+            .chapter()
+            .get()
+            .chapter_id(&id)
+            .build()?
+            .send()
+            .await?;
+
+        chapter_data
+            .data
+            .relationships
+            .iter()
+            .find(|rel| rel.type_ == RelationshipType::Manga)
+            .map(|rel| rel.id)
+            .with_context(|| format!("Chapter {} belongs to no manga", id))
+    }
+
+    /// Starts fetching the manga with the given UUID, see `SeriesBuilder`.
+    pub fn series(&self, id: Uuid) -> SeriesBuilder<'_> {
+        SeriesBuilder {
+            downloader: self,
+            id,
+            all_volume_covers: false,
+            chapter: None,
+            cover_locales: CoverLocales::default(),
+            cover_policy: CoverPolicy::default(),
+            cover_thumbnails: false,
+            credits: None,
+            download_covers: false,
+            output: PathBuf::from("{title}"),
+            package_chapters: false,
+            preferred_groups: Vec::new(),
+            processing: Processing::default(),
+            reading_direction: None,
+            title: None,
+            translated_language: Language::English,
+            volume_grouping: VolumeGrouping::default(),
+        }
+    }
+}
+
+impl<'a> SeriesBuilder<'a> {
+    /// Also download the covers of volumes without any chapters to
+    /// download, whether mangadex has none or they were not selected.
+    pub fn all_volume_covers(mut self, all_volume_covers: bool) -> Self {
+        self.all_volume_covers = all_volume_covers;
+        self
+    }
+
+    /// Only fetch this chapter, like the one a chapter link points at. It
+    /// is kept whatever its language and whichever release of it the
+    /// preferred groups would pick.
+    pub fn chapter(mut self, id: Option<Uuid>) -> Self {
+        self.chapter = id;
+        self
+    }
+
+    /// The locales to look for covers in, defaults to Japanese and then
+    /// any other.
+    pub fn cover_locales(mut self, locales: CoverLocales) -> Self {
+        self.cover_locales = locales;
+        self
+    }
+
+    /// Which covers to download when a volume has several, defaults to the
+    /// first uploaded.
+    pub fn cover_policy(mut self, policy: CoverPolicy) -> Self {
+        self.cover_policy = policy;
+        self
+    }
+
+    /// Also save the 256 and 512 pixel wide thumbnails mangadex makes of
+    /// the main cover.
+    pub fn cover_thumbnails(mut self, cover_thumbnails: bool) -> Self {
+        self.cover_thumbnails = cover_thumbnails;
+        self
+    }
+
+    /// Leave the credit and recruitment pages of scanlation groups out of
+    /// packages, or delete them, with `Manga::remove_credits`.
+    pub fn credit_filter(mut self, filter: Option<CreditFilter>) -> Self {
+        self.credits = filter;
+        self
+    }
+
+    /// Download the covers of every volume, and the manga's main cover
+    /// into its folder.
+    pub fn download_covers(mut self, download_covers: bool) -> Self {
+        self.download_covers = download_covers;
+        self
+    }
+
+    /// The folder the manga is saved in, `{title}` is replaced with the
+    /// manga's title.
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = output.into();
+        self
+    }
+
+    /// Package every chapter on its own, the chapters of volumes too.
+    pub fn package_chapters(mut self, package_chapters: bool) -> Self {
+        self.package_chapters = package_chapters;
+        self
+    }
+
+    /// Scanlation groups to prefer when a chapter has several releases.
+    pub fn preferred_groups(mut self, groups: Vec<String>) -> Self {
+        self.preferred_groups = groups;
+        self
+    }
+
+    /// What to do to the pages of each chapter once it is downloaded,
+    /// nothing by default. Webtoon mode `auto` is turned on for mangas
+    /// tagged `Long Strip`.
+    pub fn processing(mut self, processing: Processing) -> Self {
+        self.processing = processing;
+        self
+    }
+
+    /// Read the manga in this direction instead of the one its original
+    /// language and tags suggest.
+    pub fn reading_direction(mut self, direction: Option<ReadingDirection>) -> Self {
+        self.reading_direction = direction;
+        self
+    }
+
+    /// Use this title instead of the manga's English title.
+    pub fn title(mut self, title: Option<String>) -> Self {
+        self.title = title;
+        self
+    }
+
+    /// The language we get the manga translated into, defaults to English.
+    pub fn translated_language(mut self, language: Language) -> Self {
+        self.translated_language = language;
+        self
+    }
+
+    /// How chapters without a volume are grouped into folders and
+    /// packages, defaults to keeping them together in `Vol. None`.
+    pub fn volume_grouping(mut self, grouping: VolumeGrouping) -> Self {
+        self.volume_grouping = grouping;
+        self
+    }
+
+    /// Fetches the metadata, every chapter and (if enabled) the covers of
+    /// the manga. Use `Manga::select` to narrow down the chapters before
+    /// downloading.
+    ///
+    /// # Errors
+    /// Fails if any of the requests to mangadex fail.
+    pub async fn fetch(self) -> Result<Manga> {
+        let mut metadata = self.fetch_metadata().await?;
+        if let Some(direction) = self.reading_direction {
+            metadata.reading_direction = direction;
+        }
+        let title = match &self.title {
+            Some(title) => title,
+            None => metadata
+                .title
+                .get(&Language::English)
+                .context("No English title!")?,
+        };
+        let path: PathBuf = self
+            .output
+            .to_str()
+            .context("Output is not valid UTF-8!")?
+            .replace("{title}", &file_name(title))
+            .into();
+        let title = title.clone();
+        let volumes = self.fetch_chapters(&path).await?;
+        let mut processing = self.processing;
+        processing.direction = metadata.reading_direction;
+        if processing.webtoon == Webtoon::Auto && metadata.long_strip {
+            processing.webtoon = Webtoon::On;
+        }
+
+        Ok(Manga {
+            api: self.downloader.api.clone(),
+            all_volume_covers: self.all_volume_covers,
+            cover_thumbnails: self.cover_thumbnails,
+            credits: self.credits,
+            credit_hashes: Mutex::default(),
+            id: self.id,
+            language: self.translated_language,
+            metadata,
+            package_chapters: self.package_chapters,
+            processing,
+            series_cover: self.download_covers,
+            title,
+            volume_grouping: self.volume_grouping,
+            volumes,
+            path,
+        })
+    }
+
+    async fn fetch_covers(&self, path: &Path) -> Result<HashMap<Option<u32>, Vec<Cover>>> {
+        let client = &self.downloader.api.client;
+        let mut covers: Vec<Cover> = Vec::new();
+        let mut offset = 0;
+        const COVER_LIMIT: u32 = 10;
+        loop {
+            let cover_data = client
+                .cover()
+                .list()
+                .limit(COVER_LIMIT)
+                .offset(offset)
+                .manga_ids(vec![self.id])
+                .build()?
+                .send()
+                .await?;
+
+            for cover in cover_data.data {
+                if let Ok(cover_item) = cover.try_into() {
+                    covers.push(cover_item);
+                }
+            }
+
+            if cover_data.limit == 0 || cover_data.limit + cover_data.offset >= cover_data.total {
+                // We do not need to paginate so
+                break;
+            }
+
+            offset += cover_data.limit;
+        }
+
+        for cover in &mut covers {
+            cover.manga_id = self.id;
+            cover.path = Some(path.join(volume_folder(cover.volume)));
+        }
+
+        let mut covers_by_volume: HashMap<Option<u32>, Vec<Cover>> = HashMap::new();
+
+        for cover in covers {
+            covers_by_volume
+                .entry(cover.volume)
+                .or_default()
+                .push(cover);
+        }
+
+        // Every locale is fetched, so the fallback can be done on our side
+        Ok(covers_by_volume
+            .into_iter()
+            .map(|(volume, found)| {
+                (
+                    volume,
+                    Cover::choose(found, &self.cover_locales, self.cover_policy),
+                )
+            })
+            .filter(|(_, chosen)| !chosen.is_empty())
+            .collect())
+    }
+
+    async fn fetch_metadata(&self) -> Result<MangaData> {
+        let manga_data = self
+            .downloader
+            .api
+            .client
+            .manga()
+            .get()
+            .includes(vec![
+                ReferenceExpansionResource::Author,
+                ReferenceExpansionResource::CoverArt,
+            ])
+            .manga_id(&self.id)
+            .build()?
+            .send()
+            .await?;
+
+        Ok(manga_data.data.into())
+    }
+
+    /// The chapter with the given UUID, which must be one of this manga's
+    /// and hosted on mangadex.
+    async fn fetch_chapter(&self, id: Uuid) -> Result<Chapter> {
+        let chapter_data = self
+            .downloader
+            .api
+            .client
+            .chapter()
+            .get()
+            .chapter_id(&id)
+            .includes(vec![ReferenceExpansionResource::ScanlationGroup])
+            .build()?
+            .send()
+            .await?;
+
+        let in_manga = chapter_data
+            .data
+            .relationships
+            .iter()
+            .any(|rel| rel.type_ == RelationshipType::Manga && rel.id == self.id);
+        if !in_manga {
+            bail!("Chapter {} is not a chapter of manga {}", id, self.id);
+        }
+        let chapter: Chapter = chapter_data.data.try_into()?;
+        if chapter.pages == 0 {
+            bail!("Chapter {} has no pages hosted on mangadex", id);
+        }
+        Ok(chapter)
+    }
+
+    /// Every chapter of the manga translated into the language, external
+    /// chapters left out.
+    async fn fetch_feed(&self) -> Result<Vec<Chapter>> {
+        const CHAPTER_LIMIT: u32 = 500; // Max that the mangadex api allows
+        let mut offset = 0;
+        let mut chapters: Vec<Chapter> = Vec::new();
+        loop {
+            let chapters_data = self
+                .downloader
+                .api
+                .client
+                .manga()
+                .feed()
+                .manga_id(&self.id)
+                .add_translated_language(self.translated_language)
+                .includes(vec![ReferenceExpansionResource::ScanlationGroup])
+                .offset(offset)
+                .limit(CHAPTER_LIMIT)
+                .order(MangaFeedSortOrder::Chapter(OrderDirection::Ascending))
+                .build()?
+                .send()
+                .await??;
+
+            for chapter in chapters_data.data {
+                let chapter: Chapter = chapter.try_into()?;
+                if chapter.pages == 0 {
+                    // External chapters have no pages hosted on mangadex
+                    continue;
+                }
+                chapters.push(chapter);
+            }
+
+            if chapters_data.limit == 0
+                || chapters_data.limit + chapters_data.offset >= chapters_data.total
+            {
+                // We do not need to paginate so
+                break;
+            }
+
+            // Update the offset and paginate, mangadex may return fewer
+            // chapters than we asked for
+            offset += chapters_data.limit;
+        }
+        Ok(chapters)
+    }
+
+    async fn fetch_chapters(&self, path: &Path) -> Result<Vec<Volume>> {
+        let chapters = match self.chapter {
+            Some(id) => vec![self.fetch_chapter(id).await?],
+            // Several groups can release the same chapter, only keep one of them
+            None => Chapter::dedupe(self.fetch_feed().await?, &self.preferred_groups),
+        };
+
+        // Filling volumes needs the covers even when they are not downloaded
+        let mut covers: HashMap<Option<u32>, Vec<Cover>> =
+            if self.download_covers || self.volume_grouping == VolumeGrouping::Covers {
+                self.fetch_covers(path).await?
+            } else {
+                HashMap::new()
+            };
+        let cover_volumes: Vec<u32> = covers.keys().flatten().copied().collect();
+        if !self.download_covers {
+            covers.clear();
+        }
+
+        let mut groups = self.volume_grouping.group(chapters, &cover_volumes);
+        if self.all_volume_covers {
+            for volume in covers.keys() {
+                if !groups.iter().any(|group| group.volume == *volume) {
+                    groups.push(Group {
+                        volume: *volume,
+                        folder: Some(volume_folder(*volume)),
+                        chapters: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        let mut volumes_list: Vec<Volume> = groups
+            .into_iter()
+            .map(|group| {
+                let volume_path = group
+                    .folder
+                    .as_ref()
+                    .map_or_else(|| path.to_path_buf(), |folder| path.join(folder));
+                let placed = group
+                    .chapters
+                    .into_iter()
+                    .map(|mut chapter| {
+                        chapter.path = Some(volume_path.join(chapter_folder(&chapter)));
+                        chapter
+                    })
+                    .collect();
+                Volume {
+                    // Covers are saved in the folder named after their volume
+                    covers: match &group.folder {
+                        Some(folder) if *folder == volume_folder(group.volume) => {
+                            covers.remove(&group.volume).unwrap_or_default()
+                        }
+                        _ => Vec::new(),
+                    },
+                    chapters: placed,
+                    volume: group.volume,
+                    path: Some(volume_path),
+                }
+            })
+            .collect();
+
+        // Downloading in order makes sense, the sort keeps groups of
+        // chapters without a volume in order
+        volumes_list.sort_by_key(|v| v.volume);
+        Ok(volumes_list)
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const PARTIAL_EXTENSION: &str = "part";
+
+/// `name` made safe to use as a single file or folder name, path separators
+/// and control characters are replaced with `-`.
+pub(crate) fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect()
+}
+
+/// The temporary file `path` is written to before it is complete.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(PARTIAL_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it into
+/// place once complete, so an interrupted write never leaves a truncated
+/// file at `path`.
+///
+/// # Errors
+/// Fails if the file cannot be written, the temporary file is removed.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    write_atomic_with(path, |file| Ok(file.write_all(bytes)?))
+}
+
+/// Like `write_atomic`, but `write` writes the temporary file itself.
+///
+/// # Errors
+/// Fails if `write` fails or the file cannot be written, the temporary
+/// file is removed.
+pub fn write_atomic_with(path: &Path, write: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+    let partial = partial_path(path);
+    let result = File::create(&partial)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            Ok(file.sync_all()?)
+        })
+        .and_then(|_| Ok(fs::rename(&partial, path)?));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result.with_context(|| format!("Unable to write {}", path.display()))
+}
+
+/// Removes temporary files left in `dir` by a write that was aborted.
+///
+/// # Errors
+/// Fails if the directory cannot be read.
+pub fn remove_partials(dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == PARTIAL_EXTENSION) {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_path_keeps_extension() {
+        assert_eq!(
+            partial_path(Path::new("/manga/Ch. 1/01.jpg")),
+            PathBuf::from("/manga/Ch. 1/01.jpg.part")
+        );
+    }
+
+    #[test]
+    fn file_names_stay_in_their_folder() {
+        assert_eq!(file_name("Fate/Stay Night"), "Fate-Stay Night");
+        assert_eq!(file_name("A\\B\nC"), "A-B-C");
+        assert_eq!(
+            file_name("Komi Can't Communicate"),
+            "Komi Can't Communicate"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! A local http server the mangadex client is pointed at when api requests
+//! need to be recorded, replayed or cached. The client cannot be given
+//! middleware, so every api request is routed through here instead. `AniList`
+//! lookups go through it too, under `ANILIST_PATH`.
+
+use crate::{
+    cache::{Cache, Entry, Validators},
+    cassette::{Cassette, CassetteMode, Interaction, Player},
+    progress::{ProgressEvent, ProgressSink},
+};
+use anyhow::{Context, Result};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::{
+    fmt,
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::runtime::Handle;
+use url::Url;
+
+/// The request headers passed on upstream, anything else the client sends
+/// stays on this machine.
+const FORWARDED_HEADERS: [&str; 3] = ["accept", "content-type", "user-agent"];
+
+/// Where the gateway serves `AniList` lookups, forwarded to the `AniList` url
+/// instead of mangadex.
+pub(crate) const ANILIST_PATH: &str = "/anilist";
+
+#[derive(Debug)]
+enum Mode {
+    Forward,
+    Record {
+        path: PathBuf,
+        cassette: Mutex<Cassette>,
+    },
+    Replay(Mutex<Player>),
+}
+
+struct Gateway {
+    mode: Mode,
+    cache: Option<Cache>,
+    upstream: Url,
+    anilist: Url,
+    http: reqwest::Client,
+    progress: Arc<dyn ProgressSink>,
+}
+
+// Sinks have no `Debug`, so the progress is left out
+impl fmt::Debug for Gateway {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Gateway")
+            .field("mode", &self.mode)
+            .field("cache", &self.cache)
+            .field("upstream", &self.upstream)
+            .field("anilist", &self.anilist)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Starts the gateway in the background and returns its url. Requests are
+/// forwarded to `upstream`, or to `anilist` under `ANILIST_PATH`, unless
+/// they are replayed or cached. Responses that cannot be cached are
+/// reported to `progress`.
+///
+/// # Errors
+/// Fails if it is not called from within a tokio runtime, the cassette
+/// cannot be loaded or no local port is free.
+pub(crate) fn start(
+    cassette: Option<&CassetteMode>,
+    cache: Option<Cache>,
+    upstream: Url,
+    anilist: Url,
+    http: reqwest::Client,
+    progress: Arc<dyn ProgressSink>,
+) -> Result<Url> {
+    let runtime = Handle::try_current()
+        .context("A cassette or cache can only be used from within a tokio runtime")?;
+    let mode = match cassette {
+        None => Mode::Forward,
+        Some(CassetteMode::Record(path)) => Mode::Record {
+            path: path.clone(),
+            cassette: Mutex::new(Cassette::default()),
+        },
+        Some(CassetteMode::Replay(path)) => {
+            Mode::Replay(Mutex::new(Player::new(Cassette::load(path)?)))
+        }
+    };
+    let gateway = Arc::new(Gateway {
+        mode,
+        cache,
+        upstream,
+        anilist,
+        http,
+        progress,
+    });
+
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .context("Unable to start the api gateway")?;
+    let url = Url::parse(&format!("http://{}", listener.local_addr()?))?;
+    let app = Router::new().fallback(handle).with_state(gateway);
+    runtime.spawn(axum::Server::from_tcp(listener)?.serve(app.into_make_service()));
+
+    Ok(url)
+}
+
+async fn handle(
+    State(gateway): State<Arc<Gateway>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let uri = uri
+        .path_and_query()
+        .map_or_else(|| uri.path().to_string(), ToString::to_string);
+    let headers: HeaderMap = headers
+        .into_iter()
+        .filter_map(|(name, value)| Some((name?, value)))
+        .filter(|(name, _)| FORWARDED_HEADERS.contains(&name.as_str()))
+        .collect();
+
+    if let Mode::Replay(player) = &gateway.mode {
+        let request = request_body(&body);
+        let interaction = player
+            .lock()
+            .unwrap()
+            .play(method.as_str(), &uri, request.as_deref());
+        return interaction.map_or_else(
+            || {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("{} {} is not in the cassette", method, uri),
+                )
+                    .into_response()
+            },
+            respond,
+        );
+    }
+
+    let interaction = match fetch(&gateway, method, &uri, headers, body).await {
+        Ok(interaction) => interaction,
+        Err(error) => return (StatusCode::BAD_GATEWAY, format!("{:#}", error)).into_response(),
+    };
+    if let Mode::Record { path, cassette } = &gateway.mode {
+        let saved = {
+            let mut cassette = cassette.lock().unwrap();
+            cassette.interactions.push(interaction.clone());
+            cassette.save(path)
+        };
+        if let Err(error) = saved {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", error)).into_response();
+        }
+    }
+    respond(interaction)
+}
+
+/// The body of a request as it is saved, `None` when it has none.
+fn request_body(body: &Bytes) -> Option<String> {
+    (!body.is_empty()).then(|| String::from_utf8_lossy(body).into_owned())
+}
+
+/// Answers a request from the cache if it holds a fresh response, otherwise
+/// asks upstream, revalidating a stale cached response when possible.
+async fn fetch(
+    gateway: &Gateway,
+    method: Method,
+    uri: &str,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Result<Interaction> {
+    let cache = match &gateway.cache {
+        Some(cache) if Cache::caches(method.as_str(), uri) => cache,
+        _ => return Ok(forward(gateway, method, uri, headers, body).await?.0),
+    };
+
+    // AniList lookups share a url, they are told apart by their body
+    let url = request_body(&body).map_or_else(
+        || upstream_url(gateway, uri),
+        |request| format!("{} {}", upstream_url(gateway, uri), request),
+    );
+    let cached = cache.get(&url);
+    if let Some(entry) = &cached {
+        if cache.is_fresh(entry) {
+            return Ok(entry.response.clone());
+        }
+        if let Some(etag) = entry
+            .validators
+            .etag
+            .as_deref()
+            .and_then(|v| v.parse().ok())
+        {
+            headers.insert(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = entry
+            .validators
+            .last_modified
+            .as_deref()
+            .and_then(|v| v.parse().ok())
+        {
+            headers.insert(header::IF_MODIFIED_SINCE, date);
+        }
+    }
+
+    let (interaction, validators) = forward(gateway, method, uri, headers, body).await?;
+    let entry = match cached {
+        Some(entry) if interaction.status == StatusCode::NOT_MODIFIED.as_u16() => {
+            Entry::new(url, entry.response, entry.validators)
+        }
+        _ if interaction.status == StatusCode::OK.as_u16() => {
+            Entry::new(url, interaction, validators)
+        }
+        _ => return Ok(interaction),
+    };
+    // The response is good even if it could not be cached
+    if let Err(error) = cache.put(&entry) {
+        gateway.progress.event(&ProgressEvent::CacheFailed {
+            uri: uri.to_string(),
+            error: format!("{:#}", error),
+        });
+    }
+    Ok(entry.response)
+}
+
+async fn forward(
+    gateway: &Gateway,
+    method: Method,
+    uri: &str,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(Interaction, Validators)> {
+    let request = request_body(&body);
+    let response = gateway
+        .http
+        .request(method.clone(), upstream_url(gateway, uri))
+        .headers(headers)
+        .body(body)
+        .send()
+        .await?;
+
+    let value = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string)
+    };
+    let validators = Validators {
+        etag: value(header::ETAG),
+        last_modified: value(header::LAST_MODIFIED),
+    };
+    let content_type = value(header::CONTENT_TYPE);
+
+    Ok((
+        Interaction {
+            method: method.to_string(),
+            uri: uri.to_string(),
+            request,
+            status: response.status().as_u16(),
+            content_type,
+            body: response.text().await?,
+        },
+        validators,
+    ))
+}
+
+/// The url `uri` is answered from upstream.
+fn upstream_url(gateway: &Gateway, uri: &str) -> String {
+    let (upstream, path) = match uri.strip_prefix(ANILIST_PATH) {
+        Some(path) if path.is_empty() || path.starts_with(['/', '?']) => (&gateway.anilist, path),
+        _ => (&gateway.upstream, uri),
+    };
+    format!("{}{}", upstream.as_str().trim_end_matches('/'), path)
+}
+
+fn respond(interaction: Interaction) -> Response {
+    let status = StatusCode::from_u16(interaction.status).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut response = (status, interaction.body).into_response();
+    if let Some(value) = interaction
+        .content_type
+        .and_then(|content_type| content_type.parse().ok())
+    {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    response
+}
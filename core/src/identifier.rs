@@ -0,0 +1,171 @@
+//! The ways a manga can be pointed at: mangadex links, UUIDs and the ids
+//! other sites give it.
+
+use anyhow::{anyhow, Result};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use url::Url;
+use uuid::Uuid;
+
+/// A site mangadex links mangas to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Site {
+    Anilist,
+    Kitsu,
+    MyAnimeList,
+    MangaUpdates,
+}
+
+/// Each site with the prefix used on the command line.
+const SITES: [(&str, Site); 4] = [
+    ("anilist", Site::Anilist),
+    ("kitsu", Site::Kitsu),
+    ("mal", Site::MyAnimeList),
+    ("mu", Site::MangaUpdates),
+];
+
+/// What a manga was given as, written as a mangadex title or chapter link,
+/// a UUID, or a site prefix and id like `anilist:97852`. Kitsu and
+/// mangaupdates ids also need a title, see `Site::has_titles`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identifier {
+    Series(Uuid),
+    /// A chapter, which stands for its manga
+    Chapter(Uuid),
+    Link {
+        site: Site,
+        id: String,
+    },
+}
+
+impl Site {
+    /// Whether the titles of a manga can be looked up by its id on this
+    /// site, through `AniList`. Mangadex cannot be searched by the ids of
+    /// other sites, so Kitsu and mangaupdates ids are only matched against
+    /// the `links` of the results of searching for a title given with them.
+    pub fn has_titles(self) -> bool {
+        matches!(self, Self::Anilist | Self::MyAnimeList)
+    }
+
+    /// The key of the site's id in `MangaData::links`.
+    pub fn link_key(self) -> &'static str {
+        match self {
+            Self::Anilist => "al",
+            Self::Kitsu => "kt",
+            Self::MyAnimeList => "mal",
+            Self::MangaUpdates => "mu",
+        }
+    }
+}
+
+impl Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (prefix, _) = SITES
+            .iter()
+            .find(|(_, site)| site == self)
+            .expect("Every site has a prefix");
+        write!(f, "{}", prefix)
+    }
+}
+
+/// The manga or chapter a mangadex link points at, like
+/// `https://mangadex.org/title/<uuid>/komi-can-t-communicate`.
+fn mangadex_link(s: &str) -> Option<Identifier> {
+    let url = Url::parse(s).ok()?;
+    let host = url.host_str()?;
+    if host != "mangadex.org" && !host.ends_with(".mangadex.org") {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    let kind = segments.next()?;
+    let id = segments.next()?.parse().ok()?;
+    match kind {
+        "title" | "manga" => Some(Identifier::Series(id)),
+        "chapter" => Some(Identifier::Chapter(id)),
+        _ => None,
+    }
+}
+
+impl FromStr for Identifier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let link = s.split_once(':').and_then(|(prefix, id)| {
+            SITES
+                .iter()
+                .find(|(name, _)| *name == prefix)
+                .filter(|_| !id.is_empty())
+                .map(|(_, site)| Self::Link {
+                    site: *site,
+                    id: id.to_string(),
+                })
+        });
+        link.or_else(|| s.parse().ok().map(Self::Series))
+            .or_else(|| mangadex_link(s))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown manga {:?}, use a mangadex title or chapter link, a UUID, or anilist:, mal:, kitsu: or mu: and an id",
+                    s
+                )
+            })
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Series(id) => write!(f, "{}", id),
+            Self::Chapter(id) => write!(f, "https://mangadex.org/chapter/{}", id),
+            Self::Link { site, id } => write!(f, "{}:{}", site, id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KOMI: &str = "a96676e5-8ae2-425e-b549-7f15dd34a6d8";
+
+    #[test]
+    fn parses_mangadex_links() {
+        let id: Uuid = KOMI.parse().unwrap();
+        for link in [
+            KOMI.to_string(),
+            format!("https://mangadex.org/title/{}", KOMI),
+            format!("https://mangadex.org/title/{}/komi-can-t-communicate", KOMI),
+        ] {
+            assert_eq!(link.parse::<Identifier>().unwrap(), Identifier::Series(id));
+        }
+        assert_eq!(
+            format!("https://mangadex.org/chapter/{}/2", KOMI)
+                .parse::<Identifier>()
+                .unwrap(),
+            Identifier::Chapter(id)
+        );
+        assert!(format!("https://example.com/title/{}", KOMI)
+            .parse::<Identifier>()
+            .is_err());
+    }
+
+    #[test]
+    fn parses_site_ids() {
+        assert_eq!(
+            "mal:123".parse::<Identifier>().unwrap(),
+            Identifier::Link {
+                site: Site::MyAnimeList,
+                id: "123".to_string()
+            }
+        );
+        assert_eq!(
+            "kitsu:komi".parse::<Identifier>().unwrap().to_string(),
+            "kitsu:komi"
+        );
+        assert_eq!(Site::Kitsu.link_key(), "kt");
+        assert!(Site::MyAnimeList.has_titles() && !Site::MangaUpdates.has_titles());
+        assert!("anilist:".parse::<Identifier>().is_err());
+        assert!("/manga/{title}".parse::<Identifier>().is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! What mangadex knows about mangas, in a shape meant for printing as JSON.
+//! The field names are part of the command line interface, tools reading
+//! them can rely on them staying the same.
+
+use crate::manga::{language_code, Manga, MangaData, ReadingDirection};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+use uuid::Uuid;
+
+/// A manga found by searching mangadex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub id: Uuid,
+    /// The English title, or any other when there is none
+    pub title: String,
+    pub alt_titles: Vec<String>,
+    pub year: Option<u16>,
+    pub status: Option<String>,
+    /// The manga's ids on other sites, like `al` for anilist
+    pub links: BTreeMap<String, String>,
+}
+
+/// A manga with the chapters selected for download.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeriesInfo {
+    pub id: Uuid,
+    /// The title used for the manga's folder and packages
+    pub title: String,
+    pub alt_titles: Vec<String>,
+    pub authors: Vec<String>,
+    pub year: Option<u16>,
+    pub status: Option<String>,
+    pub links: BTreeMap<String, String>,
+    /// The code of the language the chapters are translated into
+    pub language: String,
+    pub reading_direction: ReadingDirection,
+    pub path: PathBuf,
+    pub volumes: Vec<VolumeInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeInfo {
+    pub volume: Option<u32>,
+    pub chapters: Vec<ChapterInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChapterInfo {
+    pub id: Uuid,
+    pub chapter: u32,
+    pub sub_chapter: Option<u32>,
+    pub title: Option<String>,
+    pub groups: Vec<String>,
+    pub pages: u32,
+}
+
+fn alt_titles(metadata: &MangaData) -> Vec<String> {
+    metadata
+        .alt_titles
+        .iter()
+        .flat_map(|titles| titles.values().cloned())
+        .collect()
+}
+
+impl SearchResult {
+    pub fn new(id: Uuid, metadata: &MangaData) -> Self {
+        Self {
+            id,
+            title: metadata.main_title().cloned().unwrap_or_default(),
+            alt_titles: alt_titles(metadata),
+            year: metadata.year,
+            status: metadata.status.clone(),
+            links: metadata.links.clone(),
+        }
+    }
+}
+
+impl SeriesInfo {
+    pub fn new(manga: &Manga) -> Self {
+        Self {
+            id: manga.id,
+            title: manga.title.clone(),
+            alt_titles: alt_titles(&manga.metadata),
+            authors: manga.metadata.authors.clone(),
+            year: manga.metadata.year,
+            status: manga.metadata.status.clone(),
+            links: manga.metadata.links.clone(),
+            language: language_code(&manga.language),
+            reading_direction: manga.metadata.reading_direction,
+            path: manga.path.clone(),
+            volumes: manga
+                .volumes
+                .iter()
+                .map(|volume| VolumeInfo {
+                    volume: volume.volume,
+                    chapters: volume
+                        .chapters
+                        .iter()
+                        .map(|chapter| ChapterInfo {
+                            id: chapter.id,
+                            chapter: chapter.chapter,
+                            sub_chapter: chapter.sub_chapter,
+                            title: chapter.title.clone(),
+                            groups: chapter.groups.clone(),
+                            pages: chapter.pages,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn chapters(&self) -> impl Iterator<Item = &ChapterInfo> {
+        self.volumes
+            .iter()
+            .flat_map(|volume| volume.chapters.iter())
+    }
+}
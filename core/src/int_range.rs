@@ -2,7 +2,7 @@ use std::ops::{Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToIncl
 
 use anyhow::{bail, Context};
 
-#[derive(PartialEq, Clone, Default)]
+#[derive(PartialEq, Clone, Copy, Default)]
 pub struct IntRange {
     start: Option<u32>,
     end: Option<u32>,
@@ -0,0 +1,129 @@
+//! Resolve mangas on mangadex, list and select their chapters and download
+//! them into a folder structure ready for conversion to epub.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use manga_dl_core::{
+//!     CancellationToken, Downloader, Language, NoProgress, SeriesQuery, Selection,
+//! };
+//!
+//! let downloader = Downloader::builder().build()?;
+//! let id = downloader
+//!     .resolve(&SeriesQuery::Title {
+//!         title: "Komi Can't Communicate".into(),
+//!         anilist_id: None,
+//!         translated_language: Language::English,
+//!     })
+//!     .await?;
+//! let mut manga = downloader
+//!     .series(id)
+//!     .output("/manga/{title}")
+//!     .fetch()
+//!     .await?;
+//! manga.select(&Selection {
+//!     chapters: Some("1..=3".parse()?),
+//!     volumes: None,
+//!     chapter_id: None,
+//! });
+//! manga.download(&NoProgress, &CancellationToken::new()).await?;
+//! # Ok(())
+//! # }
+//! ```
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cognitive_complexity,
+    clippy::dbg_macro,
+    clippy::debug_assert_with_mut_call,
+    // clippy::doc_link_with_quotes,
+    clippy::doc_markdown,
+    clippy::empty_line_after_outer_attr,
+    clippy::empty_structs_with_brackets,
+    clippy::float_cmp,
+    clippy::float_cmp_const,
+    clippy::float_equality_without_abs,
+    keyword_idents,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    // clippy::missing_docs_in_private_items,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::mod_module_files,
+    non_ascii_idents,
+    noop_method_call,
+    clippy::option_if_let_else,
+    clippy::print_stderr,
+    clippy::semicolon_if_nothing_returned,
+    clippy::unseparated_literal_suffix,
+    clippy::shadow_unrelated,
+    clippy::similar_names,
+    clippy::suspicious_operation_groupings,
+    unused_crate_dependencies,
+    unused_extern_crates,
+    unused_import_braces,
+    clippy::unused_self,
+    clippy::use_debug,
+    clippy::used_underscore_binding,
+    clippy::useless_let_if_seq,
+    clippy::wildcard_dependencies,
+    clippy::wildcard_imports
+)]
+use std::fmt::Display;
+// The mock server of the integration tests needs axum even without the gateway
+#[cfg(all(test, not(feature = "gateway")))]
+use axum as _;
+
+mod anilist;
+pub mod api;
+#[cfg(feature = "gateway")]
+mod cache;
+#[cfg(feature = "gateway")]
+pub mod cassette;
+pub mod credits;
+mod downloader;
+mod files;
+#[cfg(feature = "gateway")]
+mod gateway;
+pub mod identifier;
+pub mod info;
+pub mod int_range;
+pub mod manga;
+pub mod package;
+pub mod plan;
+pub mod process;
+pub mod progress;
+pub mod record;
+mod summary;
+
+#[cfg(feature = "gateway")]
+pub use cache::Cache;
+#[cfg(feature = "gateway")]
+pub use cassette::{Cassette, CassetteMode};
+pub use credits::{CreditFilter, PageHash};
+pub use downloader::{Downloader, DownloaderBuilder, SeriesBuilder, SeriesQuery};
+pub use identifier::{Identifier, Site};
+pub use info::{SearchResult, SeriesInfo};
+pub use manga::{
+    Chapter, Cover, CoverLocale, CoverLocales, CoverPolicy, Manga, MangaData, Move,
+    ReadingDirection, Selection, Volume, VolumeGrouping,
+};
+pub use mangadex_api_types_rust::Language;
+pub use package::PackageFormat;
+pub use plan::Plan;
+pub use process::{
+    Crop, Device, Margins, PageFormat, Processing, Profile, Spreads, Transcode, Webtoon,
+};
+pub use progress::{JsonSink, NoProgress, ProgressEvent, ProgressSink};
+pub use summary::Summary;
+pub use tokio_util::sync::CancellationToken;
+
+/// Returned when a download is stopped through its `CancellationToken`.
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Download cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
@@ -0,0 +1,595 @@
+use crate::{
+    api::Api,
+    credits::{CreditFilter, PageHash},
+    files::{file_name, write_atomic},
+    int_range::IntRange,
+    package::{chapter_pages, Package, PackageFormat, PackagedChapter},
+    plan::Plan,
+    process::Processing,
+    progress::{ProgressEvent, ProgressSink},
+    record::Record,
+    summary::Summary,
+    Cancelled,
+};
+use anyhow::{bail, Context, Result};
+use mangadex_api_types_rust::Language;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{create_dir_all, remove_dir, remove_file, rename},
+    mem::take,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+mod chapter;
+mod cover;
+mod grouping;
+mod mangadata;
+mod volume;
+
+pub use chapter::Chapter;
+pub use cover::{Cover, CoverLocale, CoverLocales, CoverPolicy};
+pub use grouping::VolumeGrouping;
+pub(crate) use grouping::{chapter_folder, volume_folder, Group};
+pub use mangadata::{MangaData, ReadingDirection};
+pub use volume::Volume;
+
+/// The code mangadex uses for a language, like `en` or `pt-br`.
+pub(crate) fn language_code(language: &Language) -> String {
+    // Languages only know their code through serde
+    match serde_json::to_value(language) {
+        Ok(serde_json::Value::String(code)) => code,
+        _ => format!("{:?}", language),
+    }
+}
+
+/// Sizes of the thumbnails mangadex makes of every cover.
+const THUMBNAIL_SIZES: [u32; 2] = [256, 512];
+
+#[derive(Debug)]
+pub struct Manga {
+    pub api: Api,
+    /// Keep volumes for their covers when none of their chapters are
+    /// selected
+    pub all_volume_covers: bool,
+    /// Also save the thumbnails of the main cover
+    pub cover_thumbnails: bool,
+    /// Finds the credit pages of scanlation groups, to leave them out of
+    /// packages
+    pub credits: Option<CreditFilter>,
+    /// The credit hashes of the record, read the first time credit pages
+    /// are looked for
+    pub(crate) credit_hashes: Mutex<Option<BTreeMap<String, Vec<PageHash>>>>,
+    pub id: Uuid,
+    /// The language the chapters are translated into
+    pub language: Language,
+    pub metadata: MangaData,
+    /// Package every chapter on its own instead of with its volume
+    pub package_chapters: bool,
+    /// Done to the pages of every chapter once it is downloaded
+    pub processing: Processing,
+    /// Save the main cover in the manga's folder
+    pub series_cover: bool,
+    /// The title used for the manga's folder and packages
+    pub title: String,
+    /// How the chapters without a volume were grouped, which decides how
+    /// they are packaged
+    pub volume_grouping: VolumeGrouping,
+    pub volumes: Vec<Volume>,
+    pub path: PathBuf,
+}
+
+/// A downloaded chapter that belongs in another folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub id: Uuid,
+    /// The chapter number, like `3.5`
+    pub number: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Which chapters of a manga to download. Both ranges are taken into
+/// account, a chapter must be in both when both are set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Selection {
+    pub chapters: Option<IntRange>,
+    pub volumes: Option<IntRange>,
+    /// Only this chapter. Fetch the one a chapter link points at with
+    /// `SeriesBuilder::chapter`, so it is there whatever its language and
+    /// release
+    pub chapter_id: Option<Uuid>,
+}
+
+impl Selection {
+    pub fn contains(&self, chapter: &Chapter) -> bool {
+        if self.chapter_id.is_some_and(|id| id != chapter.id) {
+            return false;
+        }
+        match (&self.chapters, &self.volumes) {
+            (Some(ch_range), None) => ch_range.contains(&chapter.chapter),
+            (Some(ch_range), Some(vol_range)) => {
+                chapter
+                    .volume
+                    .is_some_and(|volume| vol_range.contains(&volume))
+                    && ch_range.contains(&chapter.chapter)
+            }
+            (None, Some(vol_range)) => chapter
+                .volume
+                .is_some_and(|volume| vol_range.contains(&volume)),
+            // If both are not set we download all chapters
+            // This is the same as if chapters = .. or 0..
+            // But not if Volumes was set
+            (None, None) => true,
+        }
+    }
+}
+
+impl Manga {
+    /// Every chapter of the manga, in volume order.
+    pub fn chapters(&self) -> impl Iterator<Item = &Chapter> {
+        self.volumes.iter().flat_map(|volume| volume.chapters.iter())
+    }
+
+    /// Only keeps the chapters in the selection, volumes left without any
+    /// chapters are dropped unless all volume covers were asked for.
+    pub fn select(&mut self, selection: &Selection) {
+        for volume in &mut self.volumes {
+            volume.chapters.retain(|chapter| selection.contains(chapter));
+        }
+        let all_volume_covers = self.all_volume_covers;
+        self.volumes.retain(|volume| {
+            !volume.chapters.is_empty() || (all_volume_covers && !volume.covers.is_empty())
+        });
+    }
+
+    /// Saves the main cover as `cover.<ext>` and `poster.<ext>` in the
+    /// manga's folder, for media servers to show, along with
+    /// `thumbnail-256.jpg` and `thumbnail-512.jpg` if enabled.
+    ///
+    /// # Errors
+    /// Fails if the cover could not be downloaded.
+    pub async fn download_cover(&self) -> Result<()> {
+        let Some(cover) = &self.metadata.cover else {
+            return Ok(());
+        };
+        create_dir_all(&self.path)?;
+
+        let bytes = cover.fetch(&self.api, &cover.file_name).await?;
+        for name in ["cover", "poster"] {
+            write_atomic(&self.path.join(cover.named(name)), &bytes)?;
+        }
+
+        if self.cover_thumbnails {
+            for size in THUMBNAIL_SIZES {
+                let thumbnail = cover
+                    .fetch(&self.api, &format!("{}.{}.jpg", cover.file_name, size))
+                    .await?;
+                write_atomic(
+                    &self.path.join(format!("thumbnail-{}.jpg", size)),
+                    &thumbnail,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The credit pages of the downloaded chapters, none without a credit
+    /// filter. The credits found are remembered in the record, so they are
+    /// recognised in the chapters of later runs too. The record is read
+    /// once, and only written when new credits are found.
+    ///
+    /// # Errors
+    /// Fails if a chapter folder or the record cannot be read or written.
+    pub fn credit_pages(&self) -> Result<Vec<PathBuf>> {
+        let Some(filter) = &self.credits else {
+            return Ok(Vec::new());
+        };
+        let chapters: Vec<(&[String], &Path)> = self
+            .chapters()
+            .filter_map(|chapter| Some((chapter.groups.as_slice(), chapter.path.as_deref()?)))
+            .filter(|(_, path)| path.is_dir())
+            .collect();
+        let mut hashes = self
+            .credit_hashes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if hashes.is_none() {
+            *hashes = Some(Record::load(&self.path)?.credits);
+        }
+        let seen = hashes.get_or_insert_with(BTreeMap::new);
+        let known = seen.clone();
+        let pages = filter.find(&chapters, seen)?;
+        if *seen != known {
+            // The download may have written the record since it was read
+            let mut record = Record::load(&self.path)?;
+            record.credits = seen.clone();
+            record.save()?;
+        }
+        Ok(pages)
+    }
+
+    /// Deletes the credit pages from disk if the credit filter is set to,
+    /// returning the pages deleted.
+    ///
+    /// # Errors
+    /// Fails if the credit pages cannot be found or a page cannot be
+    /// deleted.
+    pub fn remove_credits(&self) -> Result<Vec<PathBuf>> {
+        if !self.credits.as_ref().is_some_and(|filter| filter.delete) {
+            return Ok(Vec::new());
+        }
+        let pages = self.credit_pages()?;
+        for page in &pages {
+            remove_file(page)?;
+        }
+        Ok(pages)
+    }
+
+    /// Packages every downloaded volume into each format, and every
+    /// downloaded chapter without a volume on its own, next to the volume
+    /// folders. With `package_chapters` every chapter is packaged on its
+    /// own. Packages use the cover of their volume, or the main cover.
+    /// Chapters not on disk and credit pages are left out.
+    ///
+    /// # Errors
+    /// Fails on the first package that could not be written.
+    pub fn package(
+        &self,
+        formats: &[PackageFormat],
+        progress: &dyn ProgressSink,
+    ) -> Result<Vec<PathBuf>> {
+        self.package_volumes(formats, progress, &|_| true)
+    }
+
+    /// Packages the volumes `include` picks, see `package`.
+    fn package_volumes(
+        &self,
+        formats: &[PackageFormat],
+        progress: &dyn ProgressSink,
+        include: &dyn Fn(&Volume) -> bool,
+    ) -> Result<Vec<PathBuf>> {
+        let series_cover = self
+            .metadata
+            .cover
+            .as_ref()
+            .map(|cover| self.path.join(cover.named("cover")))
+            .filter(|path| path.is_file());
+        let new_package = |id: String, title: String, volume, number, cover, chapters| Package {
+            id: format!("urn:manga-dl:{}:{}", self.id, id),
+            series: self.title.clone(),
+            title,
+            volume,
+            number,
+            authors: self.metadata.authors.clone(),
+            language: language_code(&self.language),
+            cover,
+            direction: self.metadata.reading_direction,
+            chapters,
+        };
+
+        let credits: HashSet<PathBuf> = self.credit_pages()?.into_iter().collect();
+        let mut packages: Vec<Package> = Vec::new();
+        for volume in self.volumes.iter().filter(|volume| include(volume)) {
+            let mut chapters: Vec<(&Chapter, PackagedChapter)> = Vec::new();
+            for chapter in &volume.chapters {
+                let Some(path) = &chapter.path else { continue };
+                let mut pages = chapter_pages(path)?;
+                pages.retain(|page| !credits.contains(page));
+                if !pages.is_empty() {
+                    chapters.push((
+                        chapter,
+                        PackagedChapter {
+                            label: chapter.heading(),
+                            pages,
+                        },
+                    ));
+                }
+            }
+
+            let cover = volume
+                .covers
+                .first()
+                .and_then(|cover| Some(cover.path.as_ref()?.join(cover.saved_name(0))))
+                .filter(|path| path.is_file())
+                .or_else(|| series_cover.clone());
+            match volume.volume {
+                Some(number) if !chapters.is_empty() && !self.package_chapters => {
+                    // Volumes only grouping made up are not given as the volume
+                    let real = chapters
+                        .iter()
+                        .any(|(chapter, _)| chapter.volume == Some(number));
+                    packages.push(new_package(
+                        format!("vol-{}", number),
+                        format!("{} Vol. {}", self.title, number),
+                        real.then_some(number),
+                        None,
+                        cover,
+                        chapters.into_iter().map(|(_, packaged)| packaged).collect(),
+                    ));
+                }
+                // Groups of chapters are packaged whole, named like their folder
+                None if !self.package_chapters
+                    && matches!(self.volume_grouping, VolumeGrouping::Every(_)) =>
+                {
+                    let Some((first, _)) = chapters.first() else {
+                        continue;
+                    };
+                    let name = volume
+                        .path
+                        .as_deref()
+                        .and_then(Path::file_name)
+                        .map_or_else(
+                            || first.heading(),
+                            |name| name.to_string_lossy().into_owned(),
+                        );
+                    packages.push(new_package(
+                        format!("chs-{}", first.number()),
+                        format!("{} {}", self.title, name),
+                        None,
+                        None,
+                        cover,
+                        chapters.into_iter().map(|(_, packaged)| packaged).collect(),
+                    ));
+                }
+                _ => {
+                    for (chapter, packaged) in chapters {
+                        packages.push(new_package(
+                            format!("ch-{}", chapter.number()),
+                            format!("{} Ch. {}", self.title, chapter.number()),
+                            chapter.volume,
+                            Some(chapter.number()),
+                            cover.clone(),
+                            vec![packaged],
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut written: Vec<PathBuf> = Vec::new();
+        for package in &packages {
+            for format in formats {
+                let path = self.path.join(format!(
+                    "{}.{}",
+                    file_name(&package.title),
+                    format.extension()
+                ));
+                package.write(*format, &path)?;
+                progress.event(&ProgressEvent::PackageDone { path: path.clone() });
+                written.push(path);
+            }
+        }
+        Ok(written)
+    }
+
+    /// Replaces the volumes and chapters with the ones in `plan`, keeping
+    /// the covers of the volumes fetched, so the download does what the
+    /// plan says even if mangadex has changed since.
+    ///
+    /// # Errors
+    /// Fails if the plan is for another manga.
+    pub fn follow(&mut self, plan: &Plan) -> Result<()> {
+        if plan.id != self.id {
+            bail!("The plan is for manga {}, not {}", plan.id, self.id);
+        }
+        let mut fetched = take(&mut self.volumes);
+        self.path = plan.path.clone();
+        self.volumes = plan
+            .volumes
+            .iter()
+            .map(|planned| {
+                let covers = fetched
+                    .iter_mut()
+                    .find(|volume| volume.volume == planned.volume && volume.path == planned.path)
+                    .map(|volume| take(&mut volume.covers))
+                    .unwrap_or_default();
+                let chapters = planned
+                    .chapters
+                    .iter()
+                    .map(|chapter| {
+                        Chapter::new(
+                            chapter.chapter,
+                            chapter.groups.clone(),
+                            chapter.id,
+                            chapter.pages,
+                            chapter.path.clone(),
+                            chapter.sub_chapter,
+                            chapter.title.clone(),
+                            chapter.volume,
+                        )
+                    })
+                    .collect();
+                Volume::new(covers, planned.volume, chapters, planned.path.clone())
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// The downloaded chapters whose folder has changed since, like
+    /// chapters saved in `Vol. None` that mangadex has since put into a
+    /// volume. Chapters whose new folder already exists are left alone.
+    ///
+    /// # Errors
+    /// Fails if the record cannot be read.
+    pub fn moves(&self) -> Result<Vec<Move>> {
+        let record = Record::load(&self.path)?;
+        Ok(self
+            .chapters()
+            .filter_map(|chapter| {
+                let recorded = record.chapters.get(&chapter.id)?;
+                let to = chapter.path.as_ref()?;
+                (recorded.path != *to && recorded.path.is_dir() && !to.exists()).then(|| Move {
+                    id: chapter.id,
+                    number: chapter.number(),
+                    from: recorded.path.clone(),
+                    to: to.clone(),
+                })
+            })
+            .collect())
+    }
+
+    /// Moves the chapters to their new folders and updates the record, so
+    /// they are not downloaded again. Folders left empty are removed.
+    ///
+    /// # Errors
+    /// Fails on the first chapter that could not be moved, the chapters
+    /// moved before it are kept in the record.
+    pub fn reorganize(&self, moves: &[Move]) -> Result<()> {
+        let mut record = Record::load(&self.path)?;
+        let result = moves.iter().try_for_each(|planned| -> Result<()> {
+            if let Some(parent) = planned.to.parent() {
+                create_dir_all(parent)?;
+            }
+            rename(&planned.from, &planned.to).with_context(|| {
+                format!(
+                    "Unable to move {} to {}",
+                    planned.from.display(),
+                    planned.to.display()
+                )
+            })?;
+            if let Some(chapter) = self.chapters().find(|chapter| chapter.id == planned.id) {
+                let pages = record
+                    .chapters
+                    .get(&planned.id)
+                    .map_or(0, |recorded| recorded.pages);
+                record.insert(chapter, pages);
+            }
+            // Only empty folders can be removed, others stay
+            if let Some(parent) = planned.from.parent().filter(|parent| *parent != self.path) {
+                let _ = remove_dir(parent);
+            }
+            Ok(())
+        });
+        record.save()?;
+        result
+    }
+
+    /// Rebuilds the packages the moves changed: the packages of the
+    /// volumes chapters were moved into or out of are written again, and
+    /// packages of chapters that now belong to a volume are deleted.
+    ///
+    /// # Errors
+    /// Fails if an old package cannot be deleted or a package cannot be
+    /// written.
+    pub fn repackage(
+        &self,
+        formats: &[PackageFormat],
+        moves: &[Move],
+        progress: &dyn ProgressSink,
+    ) -> Result<Vec<PathBuf>> {
+        for planned in moves {
+            // Chapters in `Vol. None` or the manga's folder were packaged
+            // on their own, the others with their folder
+            let title = planned
+                .from
+                .parent()
+                .filter(|parent| *parent != self.path)
+                .and_then(Path::file_name)
+                .filter(|name| name.to_str() != Some(volume_folder(None).as_str()))
+                .map_or_else(
+                    || format!("{} Ch. {}", self.title, planned.number),
+                    |name| format!("{} {}", self.title, name.to_string_lossy()),
+                );
+            for format in formats {
+                let path = self
+                    .path
+                    .join(format!("{}.{}", file_name(&title), format.extension()));
+                if path.is_file() {
+                    remove_file(path)?;
+                }
+            }
+        }
+        let changed: HashSet<&Path> = moves
+            .iter()
+            .flat_map(|planned| [planned.from.parent(), planned.to.parent()])
+            .flatten()
+            .collect();
+        self.package_volumes(formats, progress, &|volume| {
+            volume
+                .path
+                .as_deref()
+                .is_some_and(|path| changed.contains(path))
+        })
+    }
+
+    /// Downloads every volume in order, skipping chapters an earlier run
+    /// already downloaded. Cancelling the token stops the download before
+    /// the next page is started, the pages in flight are finished and the
+    /// returned summary is marked as cancelled.
+    ///
+    /// # Errors
+    /// Fails on the first volume that could not be downloaded.
+    pub async fn download(
+        &self,
+        progress: &dyn ProgressSink,
+        cancel: &CancellationToken,
+    ) -> Result<Summary> {
+        let mut record = Record::load(&self.path)?;
+        let mut summary = Summary::default();
+
+        progress.event(&ProgressEvent::SeriesStarted {
+            id: self.id,
+            title: Some(self.title.clone()),
+            volumes: self.volumes.len(),
+            chapters: self.chapters().count(),
+        });
+        for chapter in self.chapters() {
+            progress.event(&ProgressEvent::ChapterQueued {
+                id: chapter.id,
+                volume: chapter.volume,
+                chapter: chapter.chapter,
+                sub_chapter: chapter.sub_chapter,
+            });
+        }
+
+        // Media servers do without the main cover, so the chapters are
+        // downloaded even if it cannot be saved
+        if let Some(cover) = self.metadata.cover.as_ref().filter(|_| self.series_cover) {
+            progress.event(&match self.download_cover().await {
+                Ok(()) => ProgressEvent::SeriesCoverDone { id: cover.id },
+                Err(error) => ProgressEvent::SeriesCoverFailed {
+                    id: cover.id,
+                    error: format!("{:#}", error),
+                },
+            });
+        }
+
+        for volume in self.volumes.iter() {
+            let result = if cancel.is_cancelled() {
+                Err(Cancelled.into())
+            } else {
+                volume
+                    .download(
+                        &self.api,
+                        &self.processing,
+                        progress,
+                        cancel,
+                        &mut record,
+                        &mut summary,
+                    )
+                    .await
+            };
+            match result {
+                Ok(()) => {}
+                Err(error) if error.is::<Cancelled>() => {
+                    summary.cancelled = true;
+                    break;
+                }
+                Err(error) => {
+                    record.save()?;
+                    return Err(error);
+                }
+            }
+        }
+        record.save()?;
+        progress.event(&ProgressEvent::SeriesDone {
+            id: self.id,
+            cancelled: summary.cancelled,
+        });
+
+        Ok(summary)
+    }
+}
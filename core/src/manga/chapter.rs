@@ -0,0 +1,335 @@
+use crate::{
+    api::Api,
+    files::{remove_partials, write_atomic},
+    process::Processing,
+    progress::{ProgressEvent, ProgressSink},
+    Cancelled,
+};
+use anyhow::{bail, Context, Result};
+use futures::{stream, StreamExt, TryFutureExt};
+use mangadex_api::utils::download::{chapter::DownloadMode, DownloadElement};
+use mangadex_api_schema_rust::{
+    v5::{ChapterAttributes, RelatedAttributes},
+    ApiObject,
+};
+use mangadex_api_types_rust::RelationshipType;
+use std::{collections::HashMap, fs::create_dir_all, path::PathBuf};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub chapter: u32,
+    pub sub_chapter: Option<u32>,
+    /// Names (or UUIDs if not included) of the scanlation groups that
+    /// released this chapter
+    pub groups: Vec<String>,
+    pub id: Uuid,
+    pub pages: u32,
+    pub path: Option<PathBuf>,
+    pub title: Option<String>,
+    pub volume: Option<u32>,
+}
+
+impl<T> TryFrom<ApiObject<ChapterAttributes, T>> for Chapter {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ApiObject<ChapterAttributes, T>) -> Result<Self, Self::Error> {
+        let chapter_n: f64 = match value.attributes.chapter {
+            Some(ch) => ch.parse().with_context(|| {
+                format!(
+                    "Unable to parse {:?} as a chapter number for chapter {}.",
+                    ch, &value.id
+                )
+            })?,
+            None => anyhow::bail!("No Chapter number for {}", &value.id),
+        };
+
+        let volume: Option<u32> = match value.attributes.volume {
+            Some(vol) => Some(vol.parse().with_context(|| {
+                format!(
+                    "Unable to parse {:?} as a volume number in chapter {}",
+                    vol, &value.id
+                )
+            })?),
+            None => None,
+        };
+
+        let sub_chapter: Option<u32> = {
+            if chapter_n.fract() > 0.0 {
+                Some((chapter_n.fract() * 10.0) as u32)
+            } else {
+                None
+            }
+        };
+
+        let groups: Vec<String> = value
+            .relationships
+            .iter()
+            .filter(|rel| rel.type_ == RelationshipType::ScanlationGroup)
+            .map(|rel| match &rel.attributes {
+                Some(RelatedAttributes::ScanlationGroup(group)) => group.name.clone(),
+                _ => rel.id.to_string(),
+            })
+            .collect();
+
+        Ok(Chapter {
+            id: value.id,
+            groups,
+            title: Some(value.attributes.title),
+            volume,
+            chapter: chapter_n as u32,
+            sub_chapter,
+            path: None,
+            pages: value.attributes.pages,
+        })
+    }
+}
+
+impl Chapter {
+    #[allow(dead_code, clippy::too_many_arguments)]
+    pub fn new(
+        chapter: u32,
+        groups: Vec<String>,
+        id: Uuid,
+        pages: u32,
+        path: Option<PathBuf>,
+        sub_chapter: Option<u32>,
+        title: Option<String>,
+        volume: Option<u32>,
+    ) -> Self {
+        Self {
+            chapter,
+            groups,
+            id,
+            pages,
+            path,
+            sub_chapter,
+            title,
+            volume,
+        }
+    }
+
+    /// The chapter number as mangadex writes it, like `3` or `3.5`.
+    pub fn number(&self) -> String {
+        self.sub_chapter.map_or_else(
+            || self.chapter.to_string(),
+            |sub| format!("{}.{}", self.chapter, sub),
+        )
+    }
+
+    /// The chapter's name in tables of contents, like `Ch. 3.5 - Title`.
+    pub fn heading(&self) -> String {
+        self.title
+            .as_deref()
+            .filter(|title| !title.is_empty())
+            .map_or_else(
+                || format!("Ch. {}", self.number()),
+                |title| format!("Ch. {} - {}", self.number(), title),
+            )
+    }
+
+    /// Where several releases of the same chapter exist only one is kept.
+    /// Releases by a group earlier in `preferred_groups` win (matched by
+    /// name case insensitively, or by UUID when mangadex did not send the
+    /// group's name), otherwise the first release is kept.
+    pub fn dedupe(chapters: Vec<Chapter>, preferred_groups: &[String]) -> Vec<Chapter> {
+        let rank = |chapter: &Chapter| {
+            preferred_groups
+                .iter()
+                .position(|preferred| {
+                    chapter
+                        .groups
+                        .iter()
+                        .any(|group| group.eq_ignore_ascii_case(preferred))
+                })
+                .unwrap_or(usize::MAX)
+        };
+
+        let mut kept: Vec<Chapter> = Vec::new();
+        let mut seen: HashMap<(u32, Option<u32>), usize> = HashMap::new();
+        for chapter in chapters {
+            match seen.get(&(chapter.chapter, chapter.sub_chapter)) {
+                Some(&index) => {
+                    if rank(&chapter) < rank(&kept[index]) {
+                        kept[index] = chapter;
+                    }
+                }
+                None => {
+                    seen.insert((chapter.chapter, chapter.sub_chapter), kept.len());
+                    kept.push(chapter);
+                }
+            }
+        }
+        kept
+    }
+
+    async fn save_page(
+        data: DownloadElement,
+        index: usize,
+        length: f64,
+        path: PathBuf,
+    ) -> anyhow::Result<usize> {
+        create_dir_all(path.clone())?;
+        let (filename, bytes) = data;
+        let filename = PathBuf::from(filename);
+        let mut result = filename.to_owned();
+        result.set_file_name(format!(
+            "{:0len$}",
+            index,
+            len = length.log10().floor() as usize + 1
+        ));
+        if let Some(ext) = filename.extension() {
+            result.set_extension(ext);
+        }
+        let bytes = bytes.context("Missing Bytes for Page!")?;
+        write_atomic(&path.join(result), &bytes)?;
+        Ok(bytes.len())
+    }
+
+    /// Runs `processing` over the downloaded pages on a blocking thread.
+    /// Returns the size of each page left in the chapter.
+    ///
+    /// # Errors
+    /// Fails if a page could not be processed.
+    pub async fn process(&self, processing: &Processing) -> Result<Vec<usize>> {
+        let path = self.path.clone().context("Missing Chapter Path!")?;
+        let processing = *processing;
+        tokio::task::spawn_blocking(move || processing.chapter(&path)).await?
+    }
+
+    /// Downloads the pages of the chapter, five at a time, into the
+    /// chapter's path. Pages not yet started when the token is cancelled
+    /// fail with `Cancelled`, pages already downloading are finished.
+    /// Returns the number of bytes saved for each page.
+    ///
+    /// # Errors
+    /// Fails if the page urls could not be fetched from the at-home server,
+    /// the result of each page is returned separately.
+    pub async fn download_stream(
+        &self,
+        api: &Api,
+        progress: &dyn ProgressSink,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<anyhow::Result<usize>>> {
+        let path = self.path.clone().context("Missing Chapter Path!")?;
+        remove_partials(&path)?;
+        let file_names = api
+            .client
+            .download()
+            .chapter(self.id)
+            .mode(DownloadMode::Normal)
+            .report(api.report)
+            .build()?
+            .build_at_home_urls()
+            .await?;
+
+        let len = file_names.len();
+        progress.event(&ProgressEvent::ChapterStarted {
+            id: self.id,
+            pages: len,
+        });
+        let mut stream = stream::iter(file_names)
+            .enumerate()
+            .map(|(index, filename)| {
+                let path = path.clone();
+                async move {
+                    if cancel.is_cancelled() {
+                        return Err(Cancelled.into());
+                    }
+                    // The client saves error responses as pages, so they
+                    // are skipped and failed here instead
+                    let mut status = None;
+                    let data = filename
+                        .download_with_checker(|_, response| {
+                            status = Some(response.status());
+                            !response.status().is_success()
+                        })
+                        .await?;
+                    if let Some(status) = status.filter(|status| !status.is_success()) {
+                        bail!("The page could not be downloaded: {}", status);
+                    }
+                    let bytes =
+                        Chapter::save_page(data, index + 1, len as f64, path).await?;
+                    Ok((index + 1, bytes))
+                }
+                .map_err(move |error: anyhow::Error| (index + 1, error))
+            })
+            .buffer_unordered(5);
+        let mut results = Vec::new();
+
+        while let Some(page) = stream.next().await {
+            match page {
+                Ok((page, bytes)) => {
+                    progress.event(&ProgressEvent::PageDone {
+                        chapter: self.id,
+                        page,
+                        bytes,
+                    });
+                    results.push(Ok(bytes));
+                }
+                Err((page, error)) => {
+                    if !error.is::<Cancelled>() {
+                        progress.event(&ProgressEvent::PageFailed {
+                            chapter: self.id,
+                            page,
+                            error: format!("{:#}", error),
+                        });
+                    }
+                    results.push(Err(error));
+                }
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(chapter: u32, group: &str) -> Chapter {
+        Chapter::new(
+            chapter,
+            vec![group.to_string()],
+            Uuid::nil(),
+            10,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn dedupe_keeps_first_release() {
+        let chapters = Chapter::dedupe(
+            vec![chapter(1, "A"), chapter(1, "B"), chapter(2, "B")],
+            &[],
+        );
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].groups, vec!["A"]);
+    }
+
+    #[test]
+    fn dedupe_prefers_groups_in_order() {
+        let chapters = Chapter::dedupe(
+            vec![chapter(1, "A"), chapter(1, "B"), chapter(1, "C")],
+            &["c".to_string(), "B".to_string()],
+        );
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].groups, vec!["C"]);
+    }
+
+    #[test]
+    fn headings_skip_empty_titles() {
+        let mut numbered = chapter(3, "A");
+        numbered.sub_chapter = Some(5);
+        assert_eq!(numbered.heading(), "Ch. 3.5");
+        numbered.title = Some(String::new());
+        assert_eq!(numbered.heading(), "Ch. 3.5");
+        numbered.title = Some("Komi".to_string());
+        assert_eq!(numbered.heading(), "Ch. 3.5 - Komi");
+    }
+}
@@ -0,0 +1,349 @@
+use super::language_code;
+use crate::{api::Api, files::write_atomic};
+use anyhow::{anyhow, Context, Result};
+use mangadex_api_schema_rust::{v5::CoverAttributes, ApiObject};
+use mangadex_api_types_rust::{Language, RelationshipType};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct Cover {
+    pub id: Uuid,
+    pub manga_id: Uuid,
+    /// The name of the cover file on the uploads server
+    pub file_name: String,
+    pub locale: Option<Language>,
+    /// Unix timestamp of when the cover was uploaded
+    pub created_at: i64,
+    pub volume: Option<u32>,
+    pub sub_volume: Option<u32>,
+    pub path: Option<PathBuf>,
+}
+
+/// One step of the locale fallback chain for covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverLocale {
+    Language(Language),
+    /// A cover in any locale
+    Any,
+}
+
+/// The locales covers are looked for in, in order, written like
+/// `en,ja,any`. The first locale a volume has covers in is used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CoverLocales(pub Vec<CoverLocale>);
+
+/// Which covers to keep when a volume has several in the chosen locale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverPolicy {
+    /// The earliest uploaded cover
+    #[default]
+    First,
+    /// The latest uploaded cover
+    Newest,
+    /// Every cover, oldest first
+    All,
+}
+
+impl Default for CoverLocales {
+    /// Japanese covers, falling back to any other.
+    fn default() -> Self {
+        Self(vec![
+            CoverLocale::Language(Language::Japanese),
+            CoverLocale::Any,
+        ])
+    }
+}
+
+impl FromStr for CoverLocale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "any" => Ok(Self::Any),
+            code => Language::from_str(code)
+                .map(Self::Language)
+                .map_err(|_| anyhow!("Unknown cover locale {:?}", code)),
+        }
+    }
+}
+
+impl Display for CoverLocale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "any"),
+            Self::Language(language) => write!(f, "{}", language_code(language)),
+        }
+    }
+}
+
+impl FromStr for CoverLocales {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(CoverLocale::from_str)
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+impl TryFrom<String> for CoverLocales {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<CoverLocales> for String {
+    fn from(value: CoverLocales) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for CoverLocales {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let locales: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", locales.join(","))
+    }
+}
+
+impl FromStr for CoverPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "first" => Ok(Self::First),
+            "newest" => Ok(Self::Newest),
+            "all" => Ok(Self::All),
+            _ => Err(anyhow!(
+                "Unknown cover policy {:?}, use first, newest or all",
+                s
+            )),
+        }
+    }
+}
+
+impl Cover {
+    #[allow(dead_code, clippy::too_many_arguments)]
+    pub fn new(
+        id: Uuid,
+        manga_id: Uuid,
+        file_name: String,
+        locale: Option<Language>,
+        created_at: i64,
+        volume: Option<u32>,
+        sub_volume: Option<u32>,
+        path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            id,
+            manga_id,
+            file_name,
+            locale,
+            created_at,
+            volume,
+            sub_volume,
+            path,
+        }
+    }
+
+    /// Picks the covers of one volume to download: those in the first
+    /// locale of the chain the volume has any covers in, narrowed down by
+    /// the policy. Ties between upload times are broken by id so the choice
+    /// never depends on the order mangadex listed them in.
+    pub fn choose(
+        mut covers: Vec<Cover>,
+        locales: &CoverLocales,
+        policy: CoverPolicy,
+    ) -> Vec<Cover> {
+        covers.sort_by_key(|cover| (cover.created_at, cover.id));
+        let mut chosen: Vec<Cover> = locales
+            .0
+            .iter()
+            .map(|locale| {
+                covers
+                    .iter()
+                    .filter(|cover| match locale {
+                        CoverLocale::Any => true,
+                        CoverLocale::Language(language) => cover.locale == Some(*language),
+                    })
+                    .cloned()
+                    .collect::<Vec<Cover>>()
+            })
+            .find(|covers| !covers.is_empty())
+            .unwrap_or_default();
+
+        match policy {
+            CoverPolicy::First => chosen.truncate(1),
+            CoverPolicy::Newest => chosen = chosen.pop().into_iter().collect(),
+            CoverPolicy::All => {}
+        }
+        chosen
+    }
+
+    /// The file name the cover is saved as, `cover` for the first cover of
+    /// a volume and `cover-2`, `cover-3`... for the rest.
+    pub fn saved_name(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.named("cover"),
+            index => self.named(&format!("cover-{}", index + 1)),
+        }
+    }
+
+    /// `name` with the extension of the cover file.
+    pub fn named(&self, name: &str) -> PathBuf {
+        let mut path = PathBuf::from(name);
+        if let Some(ext) = Path::new(&self.file_name).extension() {
+            path.set_extension(ext);
+        }
+        path
+    }
+
+    /// Fetches a file of the cover from the uploads server, either the
+    /// cover itself or one of the `.256.jpg`/`.512.jpg` thumbnails mangadex
+    /// makes of it.
+    ///
+    /// # Errors
+    /// Fails if the file could not be downloaded.
+    pub async fn fetch(&self, api: &Api, file_name: &str) -> Result<Vec<u8>> {
+        Ok(api
+            .http
+            .get(api.cover_url(&self.manga_id, file_name)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+
+    /// Downloads the cover from the uploads server into the cover's path.
+    ///
+    /// # Errors
+    /// Fails if the cover has no path or could not be downloaded.
+    pub async fn download(&self, index: usize, api: &Api) -> Result<()> {
+        let path = self.path.as_ref().context("Missing Cover Path!")?;
+        create_dir_all(path)?;
+        let bytes = self.fetch(api, &self.file_name).await?;
+        write_atomic(&path.join(self.saved_name(index)), &bytes)
+    }
+}
+
+impl<T> TryFrom<ApiObject<CoverAttributes, T>> for Cover {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ApiObject<CoverAttributes, T>) -> Result<Self, Self::Error> {
+        let volume_n: Option<f64> = value.attributes.volume.and_then(|volume| {
+            volume
+                .parse::<f64>()
+                .with_context(|| {
+                    format!(
+                        "Unable to parse {:?} as a chapter number for chapter {}.",
+                        volume, &value.id
+                    )
+                })
+                .ok()
+        });
+
+        let sub_volume = volume_n.and_then(|volume| {
+            if volume.fract() > 0.0 {
+                Some((volume.fract() * 10.0) as u32)
+            } else {
+                None
+            }
+        });
+
+        let manga_id = value
+            .relationships
+            .iter()
+            .find(|rel| rel.type_ == RelationshipType::Manga)
+            .map(|rel| rel.id)
+            .unwrap_or_default();
+
+        Ok(Cover {
+            id: value.id,
+            manga_id,
+            file_name: value.attributes.file_name,
+            locale: value.attributes.locale,
+            created_at: value.attributes.created_at.as_ref().unix_timestamp(),
+            path: None,
+            volume: volume_n.map(|volume| volume as u32),
+            sub_volume,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cover(id: u128, locale: Language, created_at: i64) -> Cover {
+        Cover::new(
+            Uuid::from_u128(id),
+            Uuid::nil(),
+            format!("{}.jpg", id),
+            Some(locale),
+            created_at,
+            Some(1),
+            None,
+            None,
+        )
+    }
+
+    fn ids(covers: &[Cover]) -> Vec<u128> {
+        covers.iter().map(|cover| cover.id.as_u128()).collect()
+    }
+
+    #[test]
+    fn locales_fall_back_in_order() {
+        let covers = vec![
+            cover(1, Language::Japanese, 20),
+            cover(2, Language::French, 10),
+        ];
+        let japanese: CoverLocales = "en,ja,any".parse().unwrap();
+        assert_eq!(
+            ids(&Cover::choose(covers.clone(), &japanese, CoverPolicy::All)),
+            vec![1]
+        );
+
+        let any: CoverLocales = "en,any".parse().unwrap();
+        assert_eq!(
+            ids(&Cover::choose(covers.clone(), &any, CoverPolicy::First)),
+            vec![2]
+        );
+
+        let english: CoverLocales = "en".parse().unwrap();
+        assert!(Cover::choose(covers, &english, CoverPolicy::All).is_empty());
+    }
+
+    #[test]
+    fn policy_picks_by_upload_time() {
+        let covers = vec![
+            cover(3, Language::Japanese, 30),
+            cover(1, Language::Japanese, 10),
+            cover(2, Language::Japanese, 20),
+        ];
+        let locales = CoverLocales::default();
+        let choose = |policy| ids(&Cover::choose(covers.clone(), &locales, policy));
+        assert_eq!(choose(CoverPolicy::First), vec![1]);
+        assert_eq!(choose(CoverPolicy::Newest), vec![3]);
+        assert_eq!(choose(CoverPolicy::All), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn primary_cover_is_named_cover() {
+        let cover = cover(1, Language::Japanese, 0);
+        assert_eq!(cover.saved_name(0), PathBuf::from("cover.jpg"));
+        assert_eq!(cover.saved_name(1), PathBuf::from("cover-2.jpg"));
+    }
+}
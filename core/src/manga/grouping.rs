@@ -0,0 +1,319 @@
+use super::chapter::Chapter;
+use crate::files::file_name;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// How the chapters mangadex lists without a volume are grouped into
+/// folders and packages, written `keep`, `covers`, `single` or a number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum VolumeGrouping {
+    /// Together in `Vol. None`, packaged one chapter at a time
+    #[default]
+    Keep,
+    /// Chapters 1 to N, N+1 to 2N and so on make a volume named after its
+    /// bounds, like `Ch. 81-90`
+    Every(u32),
+    /// Into the volume whose chapters they are between, or the volumes
+    /// that so far only have covers, as many chapters to each as the other
+    /// volumes have
+    Covers,
+    /// Each chapter straight in the manga's folder, packaged on its own
+    Single,
+}
+
+/// Chapters that share a folder.
+#[derive(Debug)]
+pub(crate) struct Group {
+    pub volume: Option<u32>,
+    /// The folder in the manga's folder, `None` for chapters saved straight
+    /// in it
+    pub folder: Option<String>,
+    pub chapters: Vec<Chapter>,
+}
+
+/// The folder of a numbered volume, or of `Vol. None`.
+pub(crate) fn volume_folder(volume: Option<u32>) -> String {
+    format!(
+        "Vol. {}",
+        volume.map_or("None".to_string(), |num| format!("{:?}", num))
+    )
+}
+
+/// The folder a chapter is saved in.
+pub(crate) fn chapter_folder(chapter: &Chapter) -> String {
+    let name = match (&chapter.title, &chapter.sub_chapter) {
+        (Some(title), Some(sub)) => format!("Ch. {}.{} - {}", chapter.chapter, sub, title),
+        (Some(title), None) => format!("Ch. {} - {}", chapter.chapter, title),
+        (None, Some(sub)) => format!("Ch. {}.{}", chapter.chapter, sub),
+        (None, None) => format!("Ch. {}", chapter.chapter),
+    };
+    file_name(&name)
+}
+
+/// The name of the `index`th run of `size` chapters, like `Ch. 81-90`.
+/// The bounds are fixed, so the name stays as chapters come out.
+fn chapter_range(index: u32, size: u32) -> String {
+    let first = index.saturating_mul(size).saturating_add(1);
+    let last = first.saturating_add(size - 1);
+    if first == last {
+        format!("Ch. {}", first)
+    } else {
+        format!("Ch. {}-{}", first, last)
+    }
+}
+
+impl VolumeGrouping {
+    /// Groups `chapters` into volumes, in order. `cover_volumes` are the
+    /// volumes mangadex has covers for, which `Covers` fills. The volume of
+    /// a chapter stays the one mangadex gave it, whichever group it is in.
+    pub(crate) fn group(self, chapters: Vec<Chapter>, cover_volumes: &[u32]) -> Vec<Group> {
+        let mut volumes: BTreeMap<u32, Vec<Chapter>> = BTreeMap::new();
+        let mut loose: Vec<Chapter> = Vec::new();
+        for chapter in chapters {
+            match chapter.volume {
+                Some(volume) => volumes.entry(volume).or_default().push(chapter),
+                None => loose.push(chapter),
+            }
+        }
+        loose.sort_by_key(|chapter| (chapter.chapter, chapter.sub_chapter));
+
+        if self == Self::Covers {
+            loose = fill_volumes(&mut volumes, loose, cover_volumes);
+        }
+
+        let mut groups: Vec<Group> = Vec::new();
+        match self {
+            _ if loose.is_empty() => {}
+            Self::Every(size) => {
+                let size = size.max(1);
+                // Chapter 0 goes with the first run
+                let mut runs: BTreeMap<u32, Vec<Chapter>> = BTreeMap::new();
+                for chapter in loose {
+                    let index = chapter.chapter.saturating_sub(1) / size;
+                    runs.entry(index).or_default().push(chapter);
+                }
+                groups.extend(runs.into_iter().map(|(index, run)| Group {
+                    volume: None,
+                    folder: Some(chapter_range(index, size)),
+                    chapters: run,
+                }));
+            }
+            Self::Single => groups.extend(loose.into_iter().map(|chapter| Group {
+                volume: None,
+                folder: None,
+                chapters: vec![chapter],
+            })),
+            Self::Keep | Self::Covers => groups.push(Group {
+                volume: None,
+                folder: Some(volume_folder(None)),
+                chapters: loose,
+            }),
+        }
+        groups.extend(volumes.into_iter().map(|(volume, numbered)| Group {
+            volume: Some(volume),
+            folder: Some(volume_folder(Some(volume))),
+            chapters: numbered,
+        }));
+        groups
+    }
+}
+
+/// Moves loose chapters into the numbered volume they fall within, then
+/// hands the chapters after the last numbered one to the volumes above it
+/// that only have covers. The chapters left over are returned.
+fn fill_volumes(
+    volumes: &mut BTreeMap<u32, Vec<Chapter>>,
+    loose: Vec<Chapter>,
+    cover_volumes: &[u32],
+) -> Vec<Chapter> {
+    let key = |chapter: &Chapter| (chapter.chapter, chapter.sub_chapter);
+    // Each volume with its first and last chapter
+    let ranges: Vec<(u32, _, _)> = volumes
+        .iter()
+        .filter_map(|(volume, chapters)| {
+            let first = chapters.iter().map(key).min()?;
+            let last = chapters.iter().map(key).max()?;
+            Some((*volume, first, last))
+        })
+        .collect();
+    let Some(highest) = ranges.iter().map(|(_, _, last)| *last).max() else {
+        return loose;
+    };
+    let known: usize = volumes.values().map(Vec::len).sum();
+    let per_volume = (known + volumes.len() / 2) / volumes.len().max(1);
+    let mut empty: Vec<u32> = cover_volumes
+        .iter()
+        .copied()
+        .filter(|volume| Some(volume) > volumes.keys().next_back())
+        .collect();
+    empty.sort_unstable();
+    empty.dedup();
+
+    let mut left = Vec::new();
+    let mut after = Vec::new();
+    for chapter in loose {
+        let within = ranges
+            .iter()
+            .find(|(_, first, last)| (*first..=*last).contains(&key(&chapter)));
+        if let Some((volume, _, _)) = within {
+            volumes.entry(*volume).or_default().push(chapter);
+        } else if key(&chapter) > highest {
+            after.push(chapter);
+        } else {
+            left.push(chapter);
+        }
+    }
+
+    let mut after = after.into_iter();
+    for volume in empty {
+        let chapters: Vec<Chapter> = after.by_ref().take(per_volume.max(1)).collect();
+        if chapters.is_empty() {
+            break;
+        }
+        volumes.insert(volume, chapters);
+    }
+    for chapters in volumes.values_mut() {
+        chapters.sort_by_key(key);
+    }
+    left.extend(after);
+    left
+}
+
+impl FromStr for VolumeGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "covers" => Ok(Self::Covers),
+            "single" => Ok(Self::Single),
+            number => match number.parse() {
+                Ok(size) if size > 0 => Ok(Self::Every(size)),
+                _ => Err(anyhow!(
+                    "Unknown volume grouping {:?}, use keep, covers, single or a number of chapters",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl Display for VolumeGrouping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep => write!(f, "keep"),
+            Self::Every(size) => write!(f, "{}", size),
+            Self::Covers => write!(f, "covers"),
+            Self::Single => write!(f, "single"),
+        }
+    }
+}
+
+impl TryFrom<String> for VolumeGrouping {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<VolumeGrouping> for String {
+    fn from(value: VolumeGrouping) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn chapter(number: u32, volume: Option<u32>) -> Chapter {
+        Chapter::new(
+            number,
+            Vec::new(),
+            Uuid::nil(),
+            10,
+            None,
+            None,
+            None,
+            volume,
+        )
+    }
+
+    fn numbers(group: &Group) -> Vec<u32> {
+        group
+            .chapters
+            .iter()
+            .map(|chapter| chapter.chapter)
+            .collect()
+    }
+
+    #[test]
+    fn parses_groupings() {
+        for grouping in ["keep", "covers", "single", "10"] {
+            assert_eq!(
+                grouping.parse::<VolumeGrouping>().unwrap().to_string(),
+                grouping
+            );
+        }
+        assert!("0".parse::<VolumeGrouping>().is_err());
+        assert!("volumes".parse::<VolumeGrouping>().is_err());
+    }
+
+    #[test]
+    fn groups_every_few_chapters() {
+        // Chapter 3 is not out, 5 is the latest
+        let chapters = [1, 2, 4, 5]
+            .into_iter()
+            .map(|number| chapter(number, None))
+            .collect();
+        let groups = VolumeGrouping::Every(2).group(chapters, &[]);
+        let folders: Vec<_> = groups.iter().map(|group| group.folder.clone()).collect();
+        assert_eq!(
+            folders,
+            vec![
+                Some("Ch. 1-2".to_string()),
+                Some("Ch. 3-4".to_string()),
+                Some("Ch. 5-6".to_string())
+            ]
+        );
+        assert_eq!(numbers(&groups[1]), vec![4]);
+        assert!(groups.iter().all(|group| group.volume.is_none()));
+        assert_eq!(chapter_range(4, 1), "Ch. 5");
+    }
+
+    #[test]
+    fn fills_volumes_with_covers() {
+        let mut chapters: Vec<Chapter> = (1..=4).map(|number| chapter(number, Some(1))).collect();
+        chapters.extend((5..=8).map(|number| chapter(number, Some(2))));
+        // Chapter 3 is missing its volume, 9 to 17 are not in a volume yet
+        chapters[2].volume = None;
+        chapters.extend((9..=17).map(|number| chapter(number, None)));
+
+        let groups = VolumeGrouping::Covers.group(chapters, &[1, 2, 3, 4]);
+        let volumes: Vec<_> = groups.iter().map(|group| group.volume).collect();
+        assert_eq!(volumes, vec![None, Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(numbers(&groups[0]), vec![17]);
+        assert_eq!(numbers(&groups[1]), vec![1, 2, 3, 4]);
+        assert_eq!(numbers(&groups[3]), vec![9, 10, 11, 12]);
+        // The chapters keep the volume mangadex gave them
+        assert_eq!(groups[1].chapters[2].volume, None);
+        assert_eq!(groups[4].chapters[0].volume, None);
+    }
+
+    #[test]
+    fn single_chapters_have_no_folder() {
+        let chapters = vec![chapter(1, Some(1)), chapter(2, None), chapter(3, None)];
+        let groups = VolumeGrouping::Single.group(chapters, &[]);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].folder, None);
+        assert_eq!(groups[2].folder, Some("Vol. 1".to_string()));
+    }
+}
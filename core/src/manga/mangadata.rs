@@ -0,0 +1,231 @@
+use super::Cover;
+use anyhow::{anyhow, Result};
+use mangadex_api_schema_rust::{
+    v5::{LocalizedString, MangaAttributes, RelatedAttributes},
+    ApiObject,
+};
+use mangadex_api_types_rust::{Language, RelationshipType};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// The id mangadex gives its `Long Strip` format tag.
+const LONG_STRIP_TAG: Uuid = uuid::uuid!("3e2b8dae-350e-4ab8-a8ce-016e844b9f0d");
+
+/// The order pages are read in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReadingDirection {
+    /// Japanese manga
+    #[default]
+    #[serde(rename = "rtl")]
+    RightToLeft,
+    /// Western comics, manhwa and manhua
+    #[serde(rename = "ltr")]
+    LeftToRight,
+    /// Webtoons, read top to bottom
+    #[serde(rename = "vertical")]
+    Vertical,
+}
+
+const DIRECTIONS: [(&str, ReadingDirection); 3] = [
+    ("rtl", ReadingDirection::RightToLeft),
+    ("ltr", ReadingDirection::LeftToRight),
+    ("vertical", ReadingDirection::Vertical),
+];
+
+impl ReadingDirection {
+    /// The usual direction for a manga first published in
+    /// `original_language`.
+    pub fn of(original_language: Language, long_strip: bool) -> Self {
+        if long_strip {
+            Self::Vertical
+        } else if original_language == Language::Japanese {
+            Self::RightToLeft
+        } else {
+            Self::LeftToRight
+        }
+    }
+}
+
+impl FromStr for ReadingDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        DIRECTIONS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, direction)| *direction)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown reading direction {:?}, use rtl, ltr or vertical",
+                    s
+                )
+            })
+    }
+}
+
+impl Display for ReadingDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = DIRECTIONS
+            .iter()
+            .find(|(_, direction)| direction == self)
+            .expect("Every reading direction has a name");
+        write!(f, "{}", name)
+    }
+}
+
+/// Required Manga metadata
+#[derive(Debug, Clone, Default)]
+pub struct MangaData {
+    pub alt_titles: Vec<LocalizedString>,
+    pub authors: Vec<String>,
+    /// The manga's main cover
+    pub cover: Option<Cover>,
+    /// The manga's ids on other sites, keyed by the codes mangadex uses:
+    /// `al` (anilist), `kt` (kitsu), `mal` (myanimelist) and `mu`
+    /// (mangaupdates)
+    pub links: BTreeMap<String, String>,
+    /// Tagged `Long Strip`, a webtoon read top to bottom
+    pub long_strip: bool,
+    pub reading_direction: ReadingDirection,
+    /// The publication status, like `ongoing` or `completed`
+    pub status: Option<String>,
+    pub title: LocalizedString,
+    pub year: Option<u16>,
+}
+
+impl MangaData {
+    #[allow(dead_code, clippy::too_many_arguments)]
+    pub fn new(
+        alt_titles: Vec<LocalizedString>,
+        authors: Vec<String>,
+        cover: Option<Cover>,
+        links: BTreeMap<String, String>,
+        long_strip: bool,
+        reading_direction: ReadingDirection,
+        status: Option<String>,
+        title: LocalizedString,
+        year: Option<u16>,
+    ) -> Self {
+        Self {
+            alt_titles,
+            authors,
+            cover,
+            links,
+            long_strip,
+            reading_direction,
+            status,
+            title,
+            year,
+        }
+    }
+
+    /// The English title, or any other when there is none.
+    pub fn main_title(&self) -> Option<&String> {
+        self.title
+            .get(&Language::English)
+            .or_else(|| self.title.values().next())
+    }
+}
+
+impl From<ApiObject<MangaAttributes>> for MangaData {
+    fn from(value: ApiObject<MangaAttributes>) -> Self {
+        let mut authors: Vec<String> = Vec::new();
+        let mut cover: Option<Cover> = None;
+        for rel in value.relationships {
+            match rel.attributes {
+                Some(RelatedAttributes::Author(data)) => authors.push(data.name),
+                Some(RelatedAttributes::CoverArt(data)) => {
+                    cover = Cover::try_from(ApiObject {
+                        id: rel.id,
+                        type_: RelationshipType::CoverArt,
+                        attributes: data,
+                        relationships: Vec::new(),
+                    })
+                    .ok()
+                    .map(|found| Cover {
+                        manga_id: value.id,
+                        ..found
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let long_strip = value.attributes.tags.iter().any(|tag| {
+            tag.id == LONG_STRIP_TAG
+                || tag
+                    .attributes
+                    .name
+                    .get(&Language::English)
+                    .map(String::as_str)
+                    == Some("Long Strip")
+        });
+
+        let links = value
+            .attributes
+            .links
+            .as_ref()
+            .map(|links| {
+                [
+                    ("al", links.anilist.clone()),
+                    ("kt", links.kitsu.clone()),
+                    ("mal", links.my_anime_list.as_ref().map(|id| id.0.clone())),
+                    ("mu", links.manga_updates.as_ref().map(|id| id.0.clone())),
+                ]
+                .into_iter()
+                .filter_map(|(site, id)| Some((site.to_string(), id?)))
+                .collect()
+            })
+            .unwrap_or_default();
+        // Statuses only know their name through serde
+        let status = serde_json::to_value(value.attributes.status)
+            .ok()
+            .and_then(|status| status.as_str().map(String::from));
+
+        MangaData {
+            links,
+            long_strip,
+            status,
+            year: value.attributes.year,
+            reading_direction: ReadingDirection::of(value.attributes.original_language, long_strip),
+            title: value.attributes.title,
+            alt_titles: value.attributes.alt_titles,
+            authors,
+            cover,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direction_from_origin() {
+        assert_eq!(
+            ReadingDirection::of(Language::Japanese, false),
+            ReadingDirection::RightToLeft
+        );
+        assert_eq!(
+            ReadingDirection::of(Language::Korean, false),
+            ReadingDirection::LeftToRight
+        );
+        assert_eq!(
+            ReadingDirection::of(Language::Korean, true),
+            ReadingDirection::Vertical
+        );
+    }
+
+    #[test]
+    fn directions_round_trip() {
+        for (name, direction) in DIRECTIONS {
+            assert_eq!(name.parse::<ReadingDirection>().unwrap(), direction);
+            assert_eq!(direction.to_string(), name);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::{chapter::Chapter, cover::Cover};
+use crate::{
+    api::Api,
+    process::Processing,
+    progress::{ProgressEvent, ProgressSink},
+    record::Record,
+    summary::Summary,
+    Cancelled,
+};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug)]
+pub struct Volume {
+    pub covers: Vec<Cover>,
+    pub volume: Option<u32>,
+    pub chapters: Vec<Chapter>,
+    pub path: Option<PathBuf>,
+}
+
+impl Volume {
+    #[allow(dead_code)]
+    pub fn new(
+        covers: Vec<Cover>,
+        volume: Option<u32>,
+        chapters: Vec<Chapter>,
+        path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            covers,
+            volume,
+            chapters,
+            path,
+        }
+    }
+
+    /// Downloads the covers and then the chapters of this volume. Chapters
+    /// in the record are skipped, every chapter downloaded is processed and
+    /// then added to the record and saved straight away.
+    ///
+    /// # Errors
+    /// Fails on the first cover or chapter that could not be downloaded, or
+    /// with `Cancelled` if the token was cancelled.
+    pub async fn download(
+        &self,
+        api: &Api,
+        processing: &Processing,
+        progress: &dyn ProgressSink,
+        cancel: &CancellationToken,
+        record: &mut Record,
+        summary: &mut Summary,
+    ) -> Result<()> {
+        progress.event(&ProgressEvent::VolumeStarted {
+            volume: self.volume,
+            covers: self.covers.len(),
+            chapters: self.chapters.len(),
+        });
+
+        for (index, cover) in self.covers.iter().enumerate() {
+            if cancel.is_cancelled() {
+                return Err(Cancelled.into());
+            }
+            cover.download(index, api).await?;
+            progress.event(&ProgressEvent::CoverDone {
+                id: cover.id,
+                volume: cover.volume,
+            });
+        }
+
+        for chapter in self.chapters.iter() {
+            if cancel.is_cancelled() {
+                return Err(Cancelled.into());
+            }
+            if record.contains(chapter) {
+                summary.skipped.push(chapter.id);
+                progress.event(&ProgressEvent::ChapterSkipped { id: chapter.id });
+                continue;
+            }
+            let result = async {
+                let pages = chapter
+                    .download_stream(api, progress, cancel)
+                    .await?
+                    .into_iter()
+                    .collect::<Result<Vec<usize>>>()?;
+                let stored = if processing.is_enabled() {
+                    chapter.process(processing).await?
+                } else {
+                    pages.clone()
+                };
+                Ok::<_, anyhow::Error>((pages, stored))
+            }
+            .await
+            .with_context(|| {
+                format!(
+                    "Attempting to download chapter {} {:?}, with an ID of {}",
+                    chapter.chapter, chapter.sub_chapter, chapter.id
+                )
+            });
+            match result {
+                Ok((pages, stored)) => {
+                    record.insert(chapter, stored.len());
+                    record.save()?;
+                    summary.downloaded.push(chapter.id);
+                    summary.pages += pages.len();
+                    summary.bytes += pages.iter().sum::<usize>() as u64;
+                    summary.stored_bytes += stored.iter().sum::<usize>() as u64;
+                    progress.event(&ProgressEvent::ChapterDone { id: chapter.id });
+                }
+                Err(error) if error.is::<Cancelled>() => return Err(error),
+                Err(error) => {
+                    progress.event(&ProgressEvent::ChapterFailed {
+                        id: chapter.id,
+                        error: format!("{:#}", error),
+                    });
+                    return Err(error);
+                }
+            }
+        }
+
+        progress.event(&ProgressEvent::VolumeDone {
+            volume: self.volume,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+//! Packaging downloaded chapters into single files for e-readers and
+//! comic servers. Volumes are packaged whole, chapters without a volume
+//! each get a package of their own.
+
+use crate::{files::write_atomic_with, manga::ReadingDirection};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+mod azw3;
+mod cbz;
+mod epub;
+mod pdf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageFormat {
+    /// A zip of the pages with a `ComicInfo.xml`
+    Cbz,
+    /// A fixed layout EPUB 3
+    Epub,
+    /// A fixed layout KF8 book for Kindles
+    Azw3,
+    /// A fixed layout EPUB with the markup Kobo readers expect
+    Kepub,
+    /// A page per image, with an outline of the chapters
+    Pdf,
+}
+
+/// Everything that goes into one package.
+#[derive(Debug, Clone)]
+pub struct Package {
+    /// Identifies the package, stays the same when it is rebuilt
+    pub id: String,
+    pub series: String,
+    pub title: String,
+    pub volume: Option<u32>,
+    /// The chapter number, for packages of a single chapter
+    pub number: Option<String>,
+    pub authors: Vec<String>,
+    pub language: String,
+    pub cover: Option<PathBuf>,
+    pub direction: ReadingDirection,
+    pub chapters: Vec<PackagedChapter>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackagedChapter {
+    pub label: String,
+    pub pages: Vec<PathBuf>,
+}
+
+const IMAGE_TYPES: [(&str, &str); 6] = [
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+];
+
+/// The media type of an image, from its extension.
+fn media_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    IMAGE_TYPES
+        .iter()
+        .find(|(image_ext, _)| *image_ext == ext)
+        .map(|(_, media_type)| *media_type)
+}
+
+/// The width and height of an image. AVIF cannot be decoded, its size is
+/// read from the `ispe` property of its primary item instead.
+///
+/// # Errors
+/// Fails if the image cannot be read or has no size.
+pub(crate) fn dimensions(path: &Path) -> Result<(u32, u32)> {
+    if media_type(path) != Some("image/avif") {
+        return image::image_dimensions(path)
+            .with_context(|| format!("Unable to read {}", path.display()));
+    }
+    avif_size(&fs::read(path)?).with_context(|| format!("No size in {}", path.display()))
+}
+
+/// The big endian number of `len` bytes at `at`.
+fn big_endian(bytes: &[u8], at: usize, len: usize) -> Option<u64> {
+    let number = bytes.get(at..at.checked_add(len)?)?;
+    Some(number.iter().fold(0, |n, byte| n << 8 | u64::from(*byte)))
+}
+
+/// The boxes an ISO media file like AVIF is made of, as the type and
+/// contents of each. Stops at the first box that does not fit.
+fn boxes(mut bytes: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut boxes = Vec::new();
+    while let Some(short) = big_endian(bytes, 0, 4) {
+        // 0 is a box up to the end, 1 a 64 bit size after the type
+        let (header, size) = match short {
+            0 => (8, bytes.len() as u64),
+            1 => match big_endian(bytes, 8, 8) {
+                Some(long) => (16, long),
+                None => break,
+            },
+            _ => (8, short),
+        };
+        let Ok(size) = usize::try_from(size) else {
+            break;
+        };
+        if size < header || size > bytes.len() {
+            break;
+        }
+        boxes.push((&bytes[4..8], &bytes[header..size]));
+        bytes = &bytes[size..];
+    }
+    boxes
+}
+
+/// The contents of the first box of type `kind` in `bytes`.
+fn child<'a>(bytes: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    boxes(bytes)
+        .into_iter()
+        .find(|(found, _)| *found == kind)
+        .map(|(_, contents)| contents)
+}
+
+/// The indices into `ipco`, counted from 1, of the properties `ipma`
+/// associates with the primary item named by `pitm`.
+fn primary_properties(meta: &[u8], iprp: &[u8]) -> Option<Vec<usize>> {
+    let pitm = child(meta, b"pitm")?;
+    let primary = big_endian(pitm, 4, if *pitm.first()? == 0 { 2 } else { 4 })?;
+
+    let ipma = child(iprp, b"ipma")?;
+    let id_len = if *ipma.first()? == 0 { 2 } else { 4 };
+    let (index_len, index_mask) = if ipma.get(3)? & 1 == 1 {
+        (2, 0x7fff)
+    } else {
+        (1, 0x7f)
+    };
+    // After the version, flags and number of entries
+    let mut at = 8;
+    for _ in 0..big_endian(ipma, 4, 4)? {
+        let id = big_endian(ipma, at, id_len)?;
+        let count = usize::from(*ipma.get(at + id_len)?);
+        at += id_len + 1;
+        if id == primary {
+            // The high bit of each index marks essential properties
+            return (0..count)
+                .map(|n| {
+                    Some((big_endian(ipma, at + n * index_len, index_len)? & index_mask) as usize)
+                })
+                .collect();
+        }
+        at += count * index_len;
+    }
+    None
+}
+
+/// The width and height of an AVIF image, from the `ispe` property in
+/// `meta` → `iprp` → `ipco` that belongs to the primary item, or the first
+/// one when the file does not say which that is.
+fn avif_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    // `meta` and `ispe` are full boxes, starting with a version and flags
+    let meta = child(bytes, b"meta")?.get(4..)?;
+    let iprp = child(meta, b"iprp")?;
+    let properties = boxes(child(iprp, b"ipco")?);
+    let (_, ispe) = primary_properties(meta, iprp)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|index| properties.get(index.checked_sub(1)?))
+        .chain(&properties)
+        .find(|(kind, _)| *kind == b"ispe")?;
+    Some((
+        u32::try_from(big_endian(ispe, 4, 4)?).ok()?,
+        u32::try_from(big_endian(ispe, 8, 4)?).ok()?,
+    ))
+}
+
+/// The pages saved in a chapter folder, in reading order.
+///
+/// # Errors
+/// Fails if the folder cannot be read.
+pub fn chapter_pages(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut pages: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    pages.retain(|page| page.is_file() && media_type(page).is_some());
+    pages.sort();
+    Ok(pages)
+}
+
+/// Escapes text for use in XML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl PackageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Cbz => "cbz",
+            Self::Epub => "epub",
+            Self::Azw3 => "azw3",
+            Self::Kepub => "kepub.epub",
+            Self::Pdf => "pdf",
+        }
+    }
+}
+
+impl FromStr for PackageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cbz" => Ok(Self::Cbz),
+            "epub" => Ok(Self::Epub),
+            "azw3" => Ok(Self::Azw3),
+            "kepub" => Ok(Self::Kepub),
+            "pdf" => Ok(Self::Pdf),
+            _ => Err(anyhow!(
+                "Unknown package format {:?}, use cbz, epub, azw3, kepub or pdf",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for PackageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Kepub => write!(f, "kepub"),
+            _ => write!(f, "{}", self.extension()),
+        }
+    }
+}
+
+impl Package {
+    pub fn pages(&self) -> impl Iterator<Item = &PathBuf> {
+        self.chapters
+            .iter()
+            .flat_map(|chapter| chapter.pages.iter())
+    }
+
+    /// Writes the package to `path`, replacing any earlier package there.
+    ///
+    /// # Errors
+    /// Fails if a page cannot be read or the package cannot be written.
+    pub fn write(&self, format: PackageFormat, path: &Path) -> Result<()> {
+        write_atomic_with(path, |file| match format {
+            PackageFormat::Cbz => cbz::write(self, file),
+            PackageFormat::Epub => epub::write(self, file, false),
+            PackageFormat::Kepub => epub::write(self, file, true),
+            PackageFormat::Pdf => pdf::write(self, file),
+            PackageFormat::Azw3 => azw3::write(self, file),
+        })
+        .with_context(|| format!("Unable to package {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(
+            escape("Komi <Can't> & \"Won't\""),
+            "Komi &lt;Can&apos;t&gt; &amp; &quot;Won&apos;t&quot;"
+        );
+    }
+
+    #[test]
+    fn media_types_from_extension() {
+        assert_eq!(media_type(Path::new("01.JPG")), Some("image/jpeg"));
+        assert_eq!(media_type(Path::new("01.png")), Some("image/png"));
+        assert_eq!(media_type(Path::new(".manga-dl.json")), None);
+    }
+
+    fn iso_box(kind: &[u8], contents: &[u8]) -> Vec<u8> {
+        let mut bytes = u32::try_from(contents.len() + 8)
+            .unwrap()
+            .to_be_bytes()
+            .to_vec();
+        bytes.extend(kind);
+        bytes.extend(contents);
+        bytes
+    }
+
+    fn ispe(width: u32, height: u32) -> Vec<u8> {
+        let mut contents = vec![0; 4];
+        contents.extend(width.to_be_bytes());
+        contents.extend(height.to_be_bytes());
+        iso_box(b"ispe", &contents)
+    }
+
+    #[test]
+    fn avif_size_from_ispe() {
+        // A grid whose 512x512 tiles (item 2) come before the full image
+        // (item 1), after data that happens to spell `ispe`
+        let ipco = [ispe(512, 512), ispe(1264, 1680)].concat();
+        let ipma = [0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 1, 1, 0, 1, 1, 0x82];
+        let iprp = [iso_box(b"ipco", &ipco), iso_box(b"ipma", &ipma)].concat();
+        let meta = [
+            vec![0; 4],
+            iso_box(b"pitm", &[0, 0, 0, 0, 0, 1]),
+            iso_box(b"iprp", &iprp),
+        ]
+        .concat();
+        let bytes = [
+            iso_box(b"ftyp", b"avifmif1"),
+            iso_box(b"free", b"\0\0\0\x14ispe\0\0\0\0\0\0\0\x01\0\0\0\x01"),
+            iso_box(b"meta", &meta),
+        ]
+        .concat();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1.avif");
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(dimensions(&path).unwrap(), (1264, 1680));
+        assert_eq!(avif_size(&bytes[..bytes.len() - 4]), None);
+    }
+
+    #[cfg(feature = "avif")]
+    #[test]
+    fn encoded_avif_size() {
+        use image::{codecs::avif::AvifEncoder, ColorType, ImageEncoder};
+
+        let mut bytes = Vec::new();
+        AvifEncoder::new_with_speed_quality(&mut bytes, 10, 60)
+            .write_image(&[90; 16 * 24 * 3], 16, 24, ColorType::Rgb8)
+            .unwrap();
+        assert_eq!(avif_size(&bytes), Some((16, 24)));
+    }
+}
@@ -0,0 +1,841 @@
+use super::{dimensions, escape, media_type, Package};
+use crate::manga::ReadingDirection;
+use anyhow::{bail, Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The text is split into records of this many bytes.
+const RECORD_SIZE: usize = 4096;
+/// A record number or index that is not there.
+const NULL: u32 = u32::MAX;
+/// The length of the MOBI header, from its `MOBI` on, for KF8 books.
+const MOBI_HEADER_LENGTH: u32 = 264;
+const INDX_HEADER_LENGTH: usize = 192;
+/// Index records are closed well before the 64 KiB a record may hold, as
+/// kindlegen does.
+const INDX_RECORD_LIMIT: usize = 0x10000 - INDX_HEADER_LENGTH - 1048;
+const CNCX_RECORD_LIMIT: usize = 0x10000 - 1024;
+/// Kindles cannot show WebP, those pages are converted to JPEG.
+const JPEG_QUALITY: u8 = 90;
+const BASE32: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+/// Closes every page, fragments are inserted before it.
+const PAGE_END: &str = "</body></html>";
+
+const FLIS: &[u8] = b"FLIS\0\0\0\x08\0\x41\0\0\0\0\0\0\xff\xff\xff\xff\0\x01\0\x03\0\0\0\x03\0\0\0\x01\xff\xff\xff\xff";
+const EOF: &[u8] = b"\xe9\x8e\r\n";
+
+/// Windows language ids, which MOBI uses for the book's locale.
+const LOCALES: [(&str, u32); 27] = [
+    ("ar", 0x01),
+    ("zh", 0x04),
+    ("cs", 0x05),
+    ("da", 0x06),
+    ("de", 0x07),
+    ("el", 0x08),
+    ("en", 0x09),
+    ("es", 0x0a),
+    ("fi", 0x0b),
+    ("fr", 0x0c),
+    ("he", 0x0d),
+    ("hu", 0x0e),
+    ("it", 0x10),
+    ("ja", 0x11),
+    ("ko", 0x12),
+    ("nl", 0x13),
+    ("no", 0x14),
+    ("pl", 0x15),
+    ("pt", 0x16),
+    ("ro", 0x18),
+    ("ru", 0x19),
+    ("sv", 0x1d),
+    ("th", 0x1e),
+    ("tr", 0x1f),
+    ("id", 0x21),
+    ("uk", 0x22),
+    ("vi", 0x2a),
+];
+
+/// An image stored in the book, read from its page when written unless it
+/// had to be converted.
+struct Resource<'a> {
+    path: &'a Path,
+    converted: Option<Vec<u8>>,
+    len: usize,
+    mime: &'static str,
+    width: u32,
+    height: u32,
+}
+
+enum Record<'a> {
+    Data(Vec<u8>),
+    Image(&'a Resource<'a>),
+}
+
+/// A tag of index entries, as the TAGX block describes it.
+struct Tag {
+    number: u8,
+    values: u8,
+    mask: u8,
+}
+
+const SKELETON_TAGS: [Tag; 2] = [
+    // The fragment count
+    Tag {
+        number: 1,
+        values: 1,
+        mask: 0b11,
+    },
+    // The start and length of the skeleton
+    Tag {
+        number: 6,
+        values: 2,
+        mask: 0b1100,
+    },
+];
+
+const FRAGMENT_TAGS: [Tag; 4] = [
+    // The selector of the element the fragment goes into, in the CNCX
+    Tag {
+        number: 2,
+        values: 1,
+        mask: 1,
+    },
+    // The file number
+    Tag {
+        number: 3,
+        values: 1,
+        mask: 2,
+    },
+    // The sequence number
+    Tag {
+        number: 4,
+        values: 1,
+        mask: 4,
+    },
+    // The start and length of the fragment
+    Tag {
+        number: 6,
+        values: 2,
+        mask: 8,
+    },
+];
+
+const NCX_TAGS: [Tag; 5] = [
+    // The position in the text
+    Tag {
+        number: 1,
+        values: 1,
+        mask: 1,
+    },
+    Tag {
+        number: 2,
+        values: 1,
+        mask: 2,
+    },
+    // The label, in the CNCX
+    Tag {
+        number: 3,
+        values: 1,
+        mask: 4,
+    },
+    // The depth
+    Tag {
+        number: 4,
+        values: 1,
+        mask: 8,
+    },
+    // The fragment and the offset into it
+    Tag {
+        number: 6,
+        values: 2,
+        mask: 128,
+    },
+];
+
+/// One entry of an index: its key and the values of each of its tags.
+type Entry = (String, Vec<Vec<u32>>);
+
+impl Resource<'_> {
+    fn bytes(&self) -> Result<Vec<u8>> {
+        match &self.converted {
+            Some(bytes) => Ok(bytes.clone()),
+            None => Ok(fs::read(self.path)?),
+        }
+    }
+
+    /// The reference pages use to show the image, `number` counts from 1.
+    fn reference(&self, number: usize) -> String {
+        format!("kindle:embed:{}?mime={}", base32(number, 4), self.mime)
+    }
+}
+
+impl Record<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Data(bytes) => bytes.len(),
+            Self::Image(resource) => resource.len,
+        }
+    }
+}
+
+fn resource(path: &Path) -> Result<Resource<'_>> {
+    let (width, height) = dimensions(path)?;
+    let (converted, mime) = match media_type(path) {
+        Some(mime @ ("image/jpeg" | "image/png" | "image/gif")) => (None, mime),
+        Some("image/avif") => bail!(
+            "Kindles cannot show AVIF pages like {}, transcode them to JPEG or PNG",
+            path.display()
+        ),
+        _ => {
+            let image = image::open(path)
+                .with_context(|| format!("Unable to read page {}", path.display()))?;
+            let mut bytes = Vec::new();
+            JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+                .encode_image(&image.to_rgb8())?;
+            (Some(bytes), "image/jpeg")
+        }
+    };
+    let len = match &converted {
+        Some(bytes) => bytes.len(),
+        None => fs::metadata(path)?.len() as usize,
+    };
+    Ok(Resource {
+        path,
+        converted,
+        len,
+        mime,
+        width,
+        height,
+    })
+}
+
+/// `number` in base 32, padded with zeros to `digits`.
+fn base32(mut number: usize, digits: usize) -> String {
+    let mut encoded = Vec::new();
+    loop {
+        encoded.push(BASE32[number % 32]);
+        number /= 32;
+        if number == 0 {
+            break;
+        }
+    }
+    encoded.resize(encoded.len().max(digits), b'0');
+    encoded
+        .iter()
+        .rev()
+        .map(|digit| char::from(*digit))
+        .collect()
+}
+
+/// A variable width integer of 7 bit groups, the last one marked by its
+/// high bit.
+fn varint(mut value: u32) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8 | 0x80];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+/// Pads `block` with zeros to a multiple of 4 bytes.
+fn aligned(mut block: Vec<u8>) -> Vec<u8> {
+    block.resize(block.len().div_ceil(4) * 4, 0);
+    block
+}
+
+fn u32s(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+/// A stable id from the package id, so rebuilt books stay the same book.
+fn unique_id(id: &str) -> u32 {
+    id.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn locale(language: &str) -> u32 {
+    let primary = language.split('-').next().unwrap_or_default();
+    LOCALES
+        .iter()
+        .find(|(code, _)| *code == primary)
+        .map_or(0, |(_, id)| *id)
+}
+
+/// Splits the text into records of `RECORD_SIZE` bytes, each followed by
+/// the bytes completing a character cut off at its end and their count.
+fn text_records(text: &[u8]) -> Vec<Vec<u8>> {
+    text.chunks(RECORD_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let rest = text.get((index + 1) * RECORD_SIZE..).unwrap_or_default();
+            let overlap = rest
+                .iter()
+                .take(3)
+                .take_while(|byte| **byte & 0xc0 == 0x80)
+                .count();
+            let mut record = chunk.to_vec();
+            record.extend(&rest[..overlap]);
+            record.push(overlap as u8);
+            record
+        })
+        .collect()
+}
+
+/// The strings an index refers to, and the offset of each.
+fn cncx(strings: &[String]) -> (Vec<Vec<u8>>, Vec<u32>) {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut offsets = Vec::new();
+    for string in strings {
+        let mut raw = varint(string.len() as u32);
+        raw.extend(string.as_bytes());
+        if record.len() + raw.len() > CNCX_RECORD_LIMIT {
+            records.push(aligned(std::mem::take(&mut record)));
+        }
+        offsets.push((records.len() * 0x10000 + record.len()) as u32);
+        record.extend(raw);
+    }
+    if !record.is_empty() {
+        records.push(aligned(record));
+    }
+    (records, offsets)
+}
+
+/// The records of an index: a header with the tag table, records of
+/// entries, then the CNCX records holding its strings.
+fn index(tags: &[Tag], entries: &[Entry], cncx: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    // The entries, their offsets, their count and last key of each record
+    let mut blocks: Vec<(Vec<u8>, Vec<u8>, u32, &str)> = vec![(Vec::new(), Vec::new(), 0, "")];
+    for (key, values) in entries {
+        let control = tags.iter().zip(values).fold(0, |control, (tag, values)| {
+            let count = (values.len() / usize::from(tag.values)) as u8;
+            control | (count << tag.mask.trailing_zeros()) & tag.mask
+        });
+        let mut entry = vec![key.len() as u8];
+        entry.extend(key.as_bytes());
+        entry.push(control);
+        for value in values.iter().flatten() {
+            entry.extend(varint(*value));
+        }
+
+        let used = blocks
+            .last()
+            .map_or(0, |(block, offsets, _, _)| block.len() + offsets.len());
+        if used + entry.len() + 2 > INDX_RECORD_LIMIT {
+            blocks.push((Vec::new(), Vec::new(), 0, ""));
+        }
+        let (block, offsets, count, last) = blocks.last_mut().expect("There is always a block");
+        offsets.extend(((INDX_HEADER_LENGTH + block.len()) as u16).to_be_bytes());
+        block.extend(entry);
+        *count += 1;
+        *last = key.as_str();
+    }
+
+    let mut records = Vec::with_capacity(blocks.len() + cncx.len() + 1);
+    let mut table = b"TAGX".to_vec();
+    table.extend(u32s(&[12 + 4 * (tags.len() as u32 + 1), 1]));
+    for tag in tags {
+        table.extend([tag.number, tag.values, tag.mask, 0]);
+    }
+    table.extend([0, 0, 0, 1]);
+    let mut geometry = Vec::new();
+    let mut idxt = b"IDXT".to_vec();
+    for (_, _, count, last) in &blocks {
+        let at = INDX_HEADER_LENGTH + table.len() + geometry.len();
+        idxt.extend((at as u16).to_be_bytes());
+        geometry.push(last.len() as u8);
+        geometry.extend(last.as_bytes());
+        geometry.extend((*count as u16).to_be_bytes());
+    }
+    let geometry = aligned(geometry);
+
+    let mut header = b"INDX".to_vec();
+    header.extend(u32s(&[
+        INDX_HEADER_LENGTH as u32,
+        0,
+        0,
+        2,
+        (INDX_HEADER_LENGTH + table.len() + geometry.len()) as u32,
+        blocks.len() as u32,
+        65001,
+        NULL,
+        entries.len() as u32,
+        0,
+        0,
+        0,
+        cncx.len() as u32,
+    ]));
+    header.resize(180, 0);
+    header.extend(u32s(&[INDX_HEADER_LENGTH as u32]));
+    header.resize(INDX_HEADER_LENGTH, 0);
+    header.extend(table);
+    header.extend(geometry);
+    header.extend(aligned(idxt));
+    records.push(header);
+
+    for (block, offsets, count, _) in blocks {
+        let block = aligned(block);
+        let mut record = b"INDX".to_vec();
+        record.extend(u32s(&[
+            INDX_HEADER_LENGTH as u32,
+            0,
+            1,
+            0,
+            (INDX_HEADER_LENGTH + block.len()) as u32,
+            count,
+            NULL,
+            NULL,
+        ]));
+        record.resize(INDX_HEADER_LENGTH, 0);
+        record.extend(block);
+        record.extend(aligned([b"IDXT".as_slice(), &offsets].concat()));
+        records.push(record);
+    }
+    records.extend(cncx);
+    records
+}
+
+fn exth(records: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut exth = b"EXTH".to_vec();
+    let length: usize = records.iter().map(|(_, data)| data.len() + 8).sum();
+    exth.extend(u32s(&[12 + length as u32, records.len() as u32]));
+    for (kind, data) in records {
+        exth.extend(u32s(&[*kind, data.len() as u32 + 8]));
+        exth.extend(data);
+    }
+    aligned(exth)
+}
+
+/// Writes a fixed layout KF8 book, as Kindles read `.azw3` files, with one
+/// page per image and the cover (if any) as the first page. Every page is
+/// a skeleton XHTML file with the image in a fragment inserted into its
+/// body, which is how KF8 stores text.
+pub(super) fn write(package: &Package, file: &mut File) -> Result<()> {
+    let cover = package.cover.as_deref().map(resource).transpose()?;
+    let pages: Vec<Resource> = package
+        .pages()
+        .map(|page| resource(page))
+        .collect::<Result<_>>()?;
+    let resources: Vec<&Resource> = cover.iter().chain(&pages).collect();
+    if resources.is_empty() {
+        bail!("Nothing to package");
+    }
+
+    // The text, and where each skeleton and fragment is in it
+    let mut text = String::new();
+    let mut skeletons: Vec<Entry> = Vec::new();
+    let mut fragments: Vec<Entry> = Vec::new();
+    let mut selectors = Vec::new();
+    let mut inserts = Vec::new();
+    for (number, resource) in resources.iter().enumerate() {
+        let body = base32(number * 2, 1);
+        let skeleton = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><!DOCTYPE html><html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title>{title}</title><meta name=\"viewport\" content=\"width={width}, height={height}\"/></head><body aid=\"{body}\" style=\"margin: 0; padding: 0;\">{PAGE_END}",
+            title = escape(&package.title),
+            width = resource.width,
+            height = resource.height,
+        );
+        let fragment = format!(
+            "<div aid=\"{aid}\"><img src=\"{src}\" alt=\"\" style=\"width: {width}px; height: {height}px;\"/></div>",
+            aid = base32(number * 2 + 1, 1),
+            src = resource.reference(number + 1),
+            width = resource.width,
+            height = resource.height,
+        );
+        let start = text.len() as u32;
+        let length = skeleton.len() as u32;
+        let insert = start + length - PAGE_END.len() as u32;
+        skeletons.push((
+            format!("SKEL{:010}", number),
+            vec![vec![1, 1], vec![start, length, start, length]],
+        ));
+        fragments.push((
+            format!("{:010}", insert),
+            vec![
+                vec![],
+                vec![number as u32],
+                vec![number as u32],
+                vec![0, fragment.len() as u32],
+            ],
+        ));
+        selectors.push(format!("P-//*[@aid='{}']", body));
+        inserts.push(insert);
+        text.push_str(&skeleton);
+        text.push_str(&fragment);
+    }
+    let (selector_records, offsets) = cncx(&selectors);
+    for ((_, values), offset) in fragments.iter_mut().zip(offsets) {
+        values[0] = vec![offset];
+    }
+
+    // One table of contents entry per chapter, at its first page
+    let mut page = usize::from(cover.is_some());
+    let mut chapters = Vec::new();
+    for chapter in &package.chapters {
+        if page < resources.len() && !chapter.pages.is_empty() {
+            chapters.push((page, chapter.label.clone()));
+        }
+        page += chapter.pages.len();
+    }
+    let (label_records, labels) = cncx(
+        &chapters
+            .iter()
+            .map(|(_, label)| label.clone())
+            .collect::<Vec<_>>(),
+    );
+    let positions: Vec<u32> = chapters.iter().map(|(first, _)| inserts[*first]).collect();
+    let key_width = format!("{:X}", chapters.len()).len().max(2);
+    let toc: Vec<Entry> = chapters
+        .iter()
+        .enumerate()
+        .map(|(index, (first, _))| {
+            let end = positions
+                .get(index + 1)
+                .copied()
+                .unwrap_or(text.len() as u32);
+            (
+                format!("{:0width$X}", index, width = key_width),
+                vec![
+                    vec![positions[index]],
+                    vec![end - positions[index]],
+                    vec![labels[index]],
+                    vec![0],
+                    vec![*first as u32, 0],
+                ],
+            )
+        })
+        .collect();
+
+    // Text, the indices, the images and the trailing records
+    let mut records: Vec<Record> = vec![Record::Data(Vec::new())];
+    let text_records = text_records(text.as_bytes());
+    let text_count = text_records.len() as u32;
+    let text_size: usize = text_records.iter().map(Vec::len).sum();
+    records.extend(text_records.into_iter().map(Record::Data));
+    // The records after the text start on a 4 byte boundary
+    let padding = text_size.div_ceil(4) * 4 - text_size;
+    if padding > 0 {
+        records.push(Record::Data(vec![0; padding]));
+    }
+    let first_non_text = records.len() as u32;
+    let fragment_index = records.len() as u32;
+    records.extend(
+        index(&FRAGMENT_TAGS, &fragments, selector_records)
+            .into_iter()
+            .map(Record::Data),
+    );
+    let skeleton_index = records.len() as u32;
+    records.extend(
+        index(&SKELETON_TAGS, &skeletons, Vec::new())
+            .into_iter()
+            .map(Record::Data),
+    );
+    let ncx_index = if toc.is_empty() {
+        NULL
+    } else {
+        let at = records.len() as u32;
+        records.extend(
+            index(&NCX_TAGS, &toc, label_records)
+                .into_iter()
+                .map(Record::Data),
+        );
+        at
+    };
+    let first_resource = records.len() as u32;
+    records.extend(resources.iter().copied().map(Record::Image));
+    let fdst_number = records.len() as u32;
+    records.push(Record::Data(
+        [b"FDST".as_slice(), &u32s(&[12, 1, 0, text.len() as u32])].concat(),
+    ));
+    let flis_number = records.len() as u32;
+    records.push(Record::Data(FLIS.to_vec()));
+    let checksum_number = records.len() as u32;
+    let mut fcis = b"FCIS\0\0\0\x14\0\0\0\x10\0\0\0\x02\0\0\0\0".to_vec();
+    fcis.extend(u32s(&[text.len() as u32]));
+    fcis.extend(b"\0\0\0\0\0\0\0\x28\0\0\0\0\0\0\0\x28\0\0\0\x08\0\x01\0\x01\0\0\0\0");
+    records.push(Record::Data(fcis));
+    records.push(Record::Data(EOF.to_vec()));
+
+    // The metadata, with the cover and how to lay out the pages
+    let rtl = package.direction == ReadingDirection::RightToLeft;
+    let string = |kind: u32, value: &str| (kind, value.as_bytes().to_vec());
+    let mut metadata: Vec<(u32, Vec<u8>)> = package
+        .authors
+        .iter()
+        .map(|author| string(100, author))
+        .collect();
+    let (width, height) = pages.iter().fold((0, 0), |(width, height), image| {
+        (width.max(image.width), height.max(image.height))
+    });
+    metadata.extend([
+        string(503, &package.title),
+        string(524, &package.language),
+        string(501, "EBOK"),
+        string(113, &package.id),
+        string(504, &package.id),
+        string(122, "true"),
+        string(123, "comic"),
+        string(124, "portrait"),
+        string(126, &format!("{}x{}", width, height)),
+        string(127, "true"),
+        string(128, "true"),
+        string(132, "false"),
+        string(
+            525,
+            if rtl {
+                "horizontal-rl"
+            } else {
+                "horizontal-lr"
+            },
+        ),
+        string(527, if rtl { "rtl" } else { "ltr" }),
+        (125, u32s(&[resources.len() as u32])),
+    ]);
+    if let Some(cover) = &cover {
+        metadata.extend([
+            (201, u32s(&[0])),
+            (202, u32s(&[0])),
+            (203, u32s(&[0])),
+            string(129, &cover.reference(1)),
+        ]);
+    }
+    let exth = exth(&metadata);
+
+    let title = package.title.as_bytes();
+    let title_offset = 16 + MOBI_HEADER_LENGTH + exth.len() as u32;
+    let mut header = vec![0, 1, 0, 0];
+    header.extend(u32s(&[text.len() as u32]));
+    header.extend((text_count as u16).to_be_bytes());
+    header.extend((RECORD_SIZE as u16).to_be_bytes());
+    header.extend([0; 4]);
+    header.extend(b"MOBI");
+    header.extend(u32s(&[
+        MOBI_HEADER_LENGTH,
+        2,
+        65001,
+        unique_id(&package.id),
+        8,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        first_non_text,
+        title_offset,
+        title.len() as u32,
+        locale(&package.language),
+        0,
+        0,
+        8,
+        first_resource,
+        0,
+        0,
+        0,
+        0,
+        0x50,
+    ]));
+    header.extend([0; 32]);
+    header.extend(u32s(&[NULL, NULL, 0, 0, 0, 0, 0]));
+    header.extend(u32s(&[
+        fdst_number,
+        1,
+        checksum_number,
+        1,
+        flis_number,
+        1,
+        0,
+        0,
+    ]));
+    header.extend(u32s(&[
+        NULL,
+        0,
+        NULL,
+        NULL,
+        1,
+        ncx_index,
+        fragment_index,
+        skeleton_index,
+        NULL,
+        NULL,
+        NULL,
+        0,
+        NULL,
+        0,
+    ]));
+    header.extend(exth);
+    header.extend(title);
+    header.extend([0, 0]);
+    records[0] = Record::Data(aligned(header));
+
+    // The Palm database holding the records
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as u32);
+    let mut name: Vec<u8> = package
+        .title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c as u8
+            } else {
+                b'_'
+            }
+        })
+        .take(31)
+        .collect();
+    name.resize(32, 0);
+    let count = records.len() as u32;
+    let mut database = name;
+    database.extend([0; 4]);
+    database.extend(u32s(&[now, now, 0, 0, 0, 0]));
+    database.extend(b"BOOKMOBI");
+    database.extend(u32s(&[2 * count - 1, 0]));
+    database.extend((count as u16).to_be_bytes());
+    let mut offset = 78 + 8 * records.len() + 2;
+    for (number, record) in records.iter().enumerate() {
+        database.extend(u32s(&[offset as u32, (2 * number as u32) & 0x00ff_ffff]));
+        offset += record.len();
+    }
+    database.extend([0, 0]);
+
+    let mut out = BufWriter::new(file);
+    out.write_all(&database)?;
+    for record in &records {
+        match record {
+            Record::Data(bytes) => out.write_all(bytes)?,
+            Record::Image(resource) => out.write_all(&resource.bytes()?)?,
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{PackageFormat, PackagedChapter};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn varints_end_with_the_high_bit() {
+        assert_eq!(varint(0), [0x80]);
+        assert_eq!(varint(0x7f), [0xff]);
+        assert_eq!(varint(0x80), [0x01, 0x80]);
+        assert_eq!(varint(300), [0x02, 0xac]);
+    }
+
+    #[test]
+    fn references_in_base32() {
+        assert_eq!(base32(1, 4), "0001");
+        assert_eq!(base32(31, 4), "000V");
+        assert_eq!(base32(32, 1), "10");
+    }
+
+    #[test]
+    fn text_records_carry_cut_characters() {
+        let mut text = vec![b'a'; RECORD_SIZE - 1];
+        text.extend("é and more".as_bytes());
+        let records = text_records(&text);
+        assert_eq!(records.len(), 2);
+        // The record keeps its size, followed by the rest of the character
+        assert_eq!(records[0].len(), RECORD_SIZE + 2);
+        assert_eq!(records[0][RECORD_SIZE..], [0xa9, 1]);
+        assert_eq!(records[1].last(), Some(&0));
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_a_kf8_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for name in ["cover.png", "1.png", "2.png"] {
+            let path = dir.path().join(name);
+            RgbImage::from_pixel(60, 80, Rgb([200, 10, 10]))
+                .save(&path)
+                .unwrap();
+            paths.push(path);
+        }
+        let package = Package {
+            id: "urn:manga-dl:test:vol-1".to_string(),
+            series: "Komi".to_string(),
+            title: "Komi Vol. 1".to_string(),
+            volume: Some(1),
+            number: None,
+            authors: vec!["Oda Tomohito".to_string()],
+            language: "en".to_string(),
+            cover: Some(paths[0].clone()),
+            direction: ReadingDirection::RightToLeft,
+            chapters: vec![
+                PackagedChapter {
+                    label: "Ch. 1".to_string(),
+                    pages: vec![paths[1].clone()],
+                },
+                PackagedChapter {
+                    label: "Ch. 2".to_string(),
+                    pages: vec![paths[2].clone()],
+                },
+            ],
+        };
+        let path = dir.path().join("Komi Vol. 1.azw3");
+        package.write(PackageFormat::Azw3, &path).unwrap();
+        let book = fs::read(&path).unwrap();
+
+        assert_eq!(&book[..12], b"Komi_Vol__1\0");
+        assert_eq!(&book[60..68], b"BOOKMOBI");
+        let count = usize::from(u16::from_be_bytes([book[76], book[77]]));
+        let record = |number: usize| {
+            let start = u32_at(&book, 78 + 8 * number) as usize;
+            let end = if number + 1 < count {
+                u32_at(&book, 78 + 8 * (number + 1)) as usize
+            } else {
+                book.len()
+            };
+            &book[start..end]
+        };
+
+        let header = record(0);
+        assert_eq!(&header[16..20], b"MOBI");
+        assert_eq!(u32_at(header, 36), 8);
+        assert_eq!(&header[280..284], b"EXTH");
+        let exth = String::from_utf8_lossy(header);
+        assert!(exth.contains("horizontal-rl"));
+        assert!(exth.contains("kindle:embed:0001?mime=image/png"));
+
+        let mut text = Vec::new();
+        for number in 1..=usize::from(u16::from_be_bytes([header[8], header[9]])) {
+            let data = record(number);
+            text.extend(&data[..data.len() - 1]);
+        }
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(text.len(), u32_at(header, 4) as usize);
+        assert!(text.contains("kindle:embed:0003?mime=image/png"));
+
+        let skeleton_index = u32_at(header, 252) as usize;
+        assert_eq!(&record(skeleton_index)[..4], b"INDX");
+        assert_eq!(&record(u32_at(header, 244) as usize)[192..196], b"TAGX");
+        let first_resource = u32_at(header, 108) as usize;
+        assert_eq!(record(first_resource), fs::read(&paths[0]).unwrap());
+        assert_eq!(&record(u32_at(header, 192) as usize)[..4], b"FDST");
+        assert_eq!(record(count - 1), EOF);
+    }
+}
@@ -0,0 +1,65 @@
+use super::{escape, Package};
+use crate::manga::ReadingDirection;
+use anyhow::Result;
+use std::{
+    fs::{self, File},
+    io::Write,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// The `ComicInfo.xml` read by Komga, Kavita and most comic readers.
+fn comic_info(package: &Package, pages: usize) -> String {
+    let mut info = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n",
+    );
+    let mut field = |name: &str, value: &str| {
+        info.push_str(&format!("  <{0}>{1}</{0}>\n", name, escape(value)));
+    };
+    field("Title", &package.title);
+    field("Series", &package.series);
+    if let Some(number) = &package.number {
+        field("Number", number);
+    }
+    if let Some(volume) = package.volume {
+        field("Volume", &volume.to_string());
+    }
+    if !package.authors.is_empty() {
+        field("Writer", &package.authors.join(", "));
+    }
+    field("PageCount", &pages.to_string());
+    field("LanguageISO", &package.language);
+    field(
+        "Manga",
+        match package.direction {
+            ReadingDirection::RightToLeft => "YesAndRightToLeft",
+            ReadingDirection::LeftToRight | ReadingDirection::Vertical => "Yes",
+        },
+    );
+    info.push_str("</ComicInfo>\n");
+    info
+}
+
+/// Writes the cover (if any) and then every page, numbered in reading
+/// order. Images are stored as they are, they do not compress any further.
+pub(super) fn write(package: &Package, file: &mut File) -> Result<()> {
+    let mut zip = ZipWriter::new(file);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let images: Vec<_> = package.cover.iter().chain(package.pages()).collect();
+    let width = images.len().to_string().len();
+    for (index, image) in images.iter().enumerate() {
+        let mut name = format!("{:0width$}", index, width = width);
+        if let Some(ext) = image.extension().and_then(|ext| ext.to_str()) {
+            name = format!("{}.{}", name, ext);
+        }
+        zip.start_file(name, stored)?;
+        zip.write_all(&fs::read(image)?)?;
+    }
+
+    zip.start_file("ComicInfo.xml", FileOptions::default())?;
+    zip.write_all(comic_info(package, images.len()).as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
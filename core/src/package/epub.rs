@@ -0,0 +1,301 @@
+use super::{dimensions, escape, media_type, Package};
+use crate::manga::ReadingDirection;
+use anyhow::Result;
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// One image and the page showing it.
+struct Item<'a> {
+    id: String,
+    image: &'a Path,
+    width: u32,
+    height: u32,
+}
+
+impl Item<'_> {
+    fn image_href(&self) -> String {
+        let ext = self.image.extension().unwrap_or_default().to_string_lossy();
+        format!("images/{}.{}", self.id, ext)
+    }
+
+    fn page_href(&self) -> String {
+        format!("pages/{}.xhtml", self.id)
+    }
+
+    /// The page showing the image. Kobo readers only keep their place in
+    /// books where the content is wrapped in their spans.
+    fn page(&self, title: &str, kobo: bool) -> String {
+        let image = format!(
+            r#"<img src="../{}" alt="" style="width: {}px; height: {}px;"/>"#,
+            self.image_href(),
+            self.width,
+            self.height
+        );
+        let body = if kobo {
+            format!(
+                r#"<div id="book-columns"><div id="book-inner"><span class="koboSpan" id="kobo.1.1">{}</span></div></div>"#,
+                image
+            )
+        } else {
+            image
+        };
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+</head>
+<body style="margin: 0; padding: 0;">
+  {body}
+</body>
+</html>
+"#,
+            title = escape(title),
+            width = self.width,
+            height = self.height,
+        )
+    }
+}
+
+/// The current time as an xsd dateTime in UTC, as `dcterms:modified` wants.
+fn modified() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch, from Howard Hinnant's algorithms
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn item(id: String, image: &Path) -> Result<Item<'_>> {
+    let (width, height) = dimensions(image)?;
+    Ok(Item {
+        id,
+        image,
+        width,
+        height,
+    })
+}
+
+fn package_document(package: &Package, cover: Option<&Item>, pages: &[Item], kobo: bool) -> String {
+    let mut metadata = format!(
+        "    <dc:identifier id=\"id\">{}</dc:identifier>\n    <dc:title>{}</dc:title>\n    <dc:language>{}</dc:language>\n",
+        escape(&package.id),
+        escape(&package.title),
+        escape(&package.language),
+    );
+    for author in &package.authors {
+        metadata.push_str(&format!(
+            "    <dc:creator>{}</dc:creator>\n",
+            escape(author)
+        ));
+    }
+    metadata.push_str(&format!(
+        "    <meta property=\"dcterms:modified\">{}</meta>\n",
+        modified()
+    ));
+    metadata.push_str(&format!(
+        "    <meta property=\"belongs-to-collection\" id=\"series\">{}</meta>\n    <meta refines=\"#series\" property=\"collection-type\">series</meta>\n",
+        escape(&package.series)
+    ));
+    if let Some(volume) = package.volume {
+        metadata.push_str(&format!(
+            "    <meta refines=\"#series\" property=\"group-position\">{}</meta>\n",
+            volume
+        ));
+    }
+    metadata.push_str(
+        "    <meta property=\"rendition:layout\">pre-paginated</meta>\n    <meta property=\"rendition:orientation\">portrait</meta>\n    <meta property=\"rendition:spread\">none</meta>\n",
+    );
+    if cover.is_some() {
+        metadata.push_str("    <meta name=\"cover\" content=\"image-cover\"/>\n");
+    }
+    if kobo {
+        // Kobo groups books into series by calibre's metadata, and takes
+        // the older fixed layout metadata more seriously than EPUB 3's
+        metadata.push_str(&format!(
+            "    <meta name=\"calibre:series\" content=\"{}\"/>\n",
+            escape(&package.series)
+        ));
+        if let Some(index) = series_index(package) {
+            metadata.push_str(&format!(
+                "    <meta name=\"calibre:series_index\" content=\"{}\"/>\n",
+                escape(&index)
+            ));
+        }
+        metadata.push_str("    <meta name=\"book-type\" content=\"comic\"/>\n    <meta name=\"fixed-layout\" content=\"true\"/>\n    <meta name=\"orientation-lock\" content=\"portrait\"/>\n");
+        if let Some((width, height)) = resolution(pages) {
+            metadata.push_str(&format!(
+                "    <meta name=\"original-resolution\" content=\"{}x{}\"/>\n",
+                width, height
+            ));
+        }
+    }
+
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    let mut spine = String::new();
+    for (index, item) in cover.into_iter().chain(pages).enumerate() {
+        let is_cover = index == 0 && cover.is_some();
+        manifest.push_str(&format!(
+            "    <item id=\"image-{id}\" href=\"{href}\" media-type=\"{media_type}\"{properties}/>\n    <item id=\"page-{id}\" href=\"{page}\" media-type=\"application/xhtml+xml\"/>\n",
+            id = item.id,
+            href = item.image_href(),
+            media_type = media_type(item.image).unwrap_or("image/jpeg"),
+            properties = if is_cover { " properties=\"cover-image\"" } else { "" },
+            page = item.page_href(),
+        ));
+        spine.push_str(&format!(
+            "    <itemref idref=\"page-{}\"{}/>\n",
+            item.id,
+            if kobo {
+                " properties=\"rendition:spread-none\""
+            } else {
+                ""
+            }
+        ));
+    }
+
+    // EPUB has no vertical progression, webtoon pages follow each other
+    let direction = match package.direction {
+        ReadingDirection::RightToLeft => "rtl",
+        ReadingDirection::LeftToRight | ReadingDirection::Vertical => "ltr",
+    };
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine page-progression-direction="{direction}">
+{spine}  </spine>
+</package>
+"#
+    )
+}
+
+/// The table of contents, one entry per chapter pointing at its first page.
+fn navigation(package: &Package, pages: &[Item]) -> String {
+    let mut entries = String::new();
+    let mut first_page = 0;
+    for chapter in &package.chapters {
+        if let Some(page) = pages.get(first_page) {
+            entries.push_str(&format!(
+                "      <li><a href=\"{}\">{}</a></li>\n",
+                page.page_href(),
+                escape(&chapter.label)
+            ));
+        }
+        first_page += chapter.pages.len();
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc">
+    <ol>
+{entries}    </ol>
+  </nav>
+</body>
+</html>
+"#,
+        title = escape(&package.title),
+    )
+}
+
+/// The position of the package in its series: the chapter number for
+/// packages of a single chapter, or the volume.
+fn series_index(package: &Package) -> Option<String> {
+    package
+        .number
+        .clone()
+        .or_else(|| package.volume.map(|volume| volume.to_string()))
+}
+
+/// The size of the largest page.
+fn resolution(pages: &[Item]) -> Option<(u32, u32)> {
+    let width = pages.iter().map(|item| item.width).max()?;
+    let height = pages.iter().map(|item| item.height).max()?;
+    Some((width, height))
+}
+
+/// Writes a fixed layout EPUB 3 with one page per image, sized to the
+/// image, and the cover (if any) as the first page. With `kobo` the pages
+/// and metadata get the additions of a KEPUB.
+pub(super) fn write(package: &Package, file: &mut File, kobo: bool) -> Result<()> {
+    let cover = package
+        .cover
+        .as_deref()
+        .map(|cover| item("cover".to_string(), cover))
+        .transpose()?;
+    let pages: Vec<Item> = package
+        .pages()
+        .enumerate()
+        .map(|(index, page)| item(format!("p{:04}", index + 1), page))
+        .collect::<Result<_>>()?;
+
+    let mut zip = ZipWriter::new(file);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default();
+
+    // The mimetype has to come first and uncompressed
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER.as_bytes())?;
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(package_document(package, cover.as_ref(), &pages, kobo).as_bytes())?;
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(navigation(package, &pages).as_bytes())?;
+
+    for item in cover.iter().chain(&pages) {
+        zip.start_file(format!("OEBPS/{}", item.image_href()), stored)?;
+        zip.write_all(&fs::read(item.image)?)?;
+        zip.start_file(format!("OEBPS/{}", item.page_href()), deflated)?;
+        zip.write_all(item.page(&package.title, kobo).as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
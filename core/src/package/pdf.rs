@@ -0,0 +1,395 @@
+use super::{media_type, Package};
+use crate::manga::ReadingDirection;
+use anyhow::{bail, Context, Result};
+use flate2::{write::ZlibEncoder, Compression};
+use image::{codecs::jpeg::JpegDecoder, ImageDecoder};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Cursor, Write},
+    path::Path,
+};
+
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const INFO: usize = 3;
+const OUTLINES: usize = 4;
+/// The objects of each page: its image, contents and the page itself.
+const PAGE_OBJECTS: usize = 3;
+
+/// A page image as a PDF image `XObject`.
+struct Image {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    /// How the samples map to the color space, when not the usual way
+    decode: Option<&'static str>,
+    filter: &'static str,
+    data: Vec<u8>,
+}
+
+/// Writes numbered objects and remembers where each starts for the cross
+/// reference table.
+struct Writer<W> {
+    out: W,
+    written: usize,
+    offsets: Vec<usize>,
+}
+
+/// The number of color components of a JPEG from its frame header, and
+/// whether it has the Adobe marker Photoshop writes, whose CMYK is stored
+/// inverted.
+fn jpeg_components(data: &[u8]) -> Option<(u8, bool)> {
+    let mut adobe = false;
+    // After the start of image marker
+    let mut at = 2;
+    loop {
+        if *data.get(at)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(at + 1)?;
+        match marker {
+            // Fill bytes before a marker
+            0xff => {
+                at += 1;
+                continue;
+            }
+            // Markers without a segment
+            0x01 | 0xd0..=0xd7 => {
+                at += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let length = usize::from(u16::from_be_bytes([*data.get(at + 2)?, *data.get(at + 3)?]));
+        let segment = data.get(at + 4..at + 2 + length)?;
+        match marker {
+            0xee if segment.starts_with(b"Adobe") => adobe = true,
+            // The start of frame markers, after the precision and size
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return Some((*segment.get(5)?, adobe));
+            }
+            // The image data starts without a frame
+            0xda => return None,
+            _ => {}
+        }
+        at += 2 + length;
+    }
+}
+
+fn image(path: &Path) -> Result<Image> {
+    match media_type(path) {
+        // JPEGs are stored as they are, PDF readers decode them
+        Some("image/jpeg") => {
+            let data = fs::read(path)?;
+            let decoder = JpegDecoder::new(Cursor::new(&data))
+                .with_context(|| format!("Unable to read page {}", path.display()))?;
+            let (width, height) = decoder.dimensions();
+            // The decoder converts CMYK to RGB, so the frame is read instead
+            let (color_space, decode) = match jpeg_components(&data) {
+                Some((1, _)) => ("/DeviceGray", None),
+                Some((4, false)) => ("/DeviceCMYK", None),
+                Some((4, true)) => ("/DeviceCMYK", Some("[1 0 1 0 1 0 1 0]")),
+                _ => ("/DeviceRGB", None),
+            };
+            Ok(Image {
+                width,
+                height,
+                color_space,
+                decode,
+                filter: "/DCTDecode",
+                data,
+            })
+        }
+        Some("image/avif") => bail!(
+            "PDFs cannot hold AVIF pages like {}, transcode them to JPEG or PNG",
+            path.display()
+        ),
+        _ => {
+            let page = image::open(path)
+                .with_context(|| format!("Unable to read page {}", path.display()))?;
+            let (color_space, pixels) = if page.color().has_color() {
+                ("/DeviceRGB", page.to_rgb8().into_raw())
+            } else {
+                ("/DeviceGray", page.to_luma8().into_raw())
+            };
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&pixels)?;
+            Ok(Image {
+                width: page.width(),
+                height: page.height(),
+                color_space,
+                decode: None,
+                filter: "/FlateDecode",
+                data: encoder.finish()?,
+            })
+        }
+    }
+}
+
+/// A PDF text string, UTF-16 so titles in any script show up.
+fn text(value: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in value.encode_utf16() {
+        hex.push_str(&format!("{:04X}", unit));
+    }
+    hex.push('>');
+    hex
+}
+
+/// The object number of the `index`th part of page `page`.
+fn page_object(page: usize, index: usize) -> usize {
+    OUTLINES + 1 + page * PAGE_OBJECTS + index
+}
+
+impl<W: Write> Writer<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out.write_all(bytes)?;
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    fn start(&mut self, number: usize) -> Result<()> {
+        if self.offsets.len() <= number {
+            self.offsets.resize(number + 1, 0);
+        }
+        self.offsets[number] = self.written;
+        self.write(format!("{} 0 obj\n", number).as_bytes())
+    }
+
+    fn object(&mut self, number: usize, dictionary: &str) -> Result<()> {
+        self.start(number)?;
+        self.write(format!("{}\nendobj\n", dictionary).as_bytes())
+    }
+
+    fn stream(&mut self, number: usize, dictionary: &str, data: &[u8]) -> Result<()> {
+        self.start(number)?;
+        self.write(format!("<< {} /Length {} >>\nstream\n", dictionary, data.len()).as_bytes())?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+
+    /// The cross reference table and trailer, which end the file.
+    fn finish(mut self) -> Result<()> {
+        let start = self.written;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len());
+        for offset in self.offsets.iter().skip(1) {
+            table.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len(),
+            CATALOG,
+            INFO,
+            start
+        ));
+        self.write(table.as_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes a PDF with one page per image at the image's size, the cover (if
+/// any) first and an outline entry per chapter.
+pub(super) fn write(package: &Package, file: &mut File) -> Result<()> {
+    let pages: Vec<&Path> = package
+        .cover
+        .as_deref()
+        .into_iter()
+        .chain(package.pages().map(|page| page.as_path()))
+        .collect();
+    if pages.is_empty() {
+        bail!("Nothing to package");
+    }
+
+    let mut pdf = Writer {
+        out: BufWriter::new(file),
+        written: 0,
+        offsets: vec![0],
+    };
+    // The binary comment marks the file as binary for transfer tools
+    pdf.write(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n")?;
+
+    let mut kids = String::new();
+    for (index, path) in pages.iter().enumerate() {
+        let image = image(path)?;
+        let (width, height) = (image.width, image.height);
+        let decode = image
+            .decode
+            .map(|decode| format!(" /Decode {}", decode))
+            .unwrap_or_default();
+        pdf.stream(
+            page_object(index, 0),
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {}{} /BitsPerComponent 8 /Filter {}",
+                width, height, image.color_space, decode, image.filter
+            ),
+            &image.data,
+        )?;
+        let contents = format!("q {} 0 0 {} 0 0 cm /Page Do Q", width, height);
+        pdf.stream(page_object(index, 1), "", contents.as_bytes())?;
+        pdf.object(
+            page_object(index, 2),
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << /Page {} 0 R >> >> /Contents {} 0 R >>",
+                PAGES,
+                width,
+                height,
+                page_object(index, 0),
+                page_object(index, 1)
+            ),
+        )?;
+        kids.push_str(&format!("{} 0 R ", page_object(index, 2)));
+    }
+    pdf.object(
+        PAGES,
+        &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.trim_end(),
+            pages.len()
+        ),
+    )?;
+
+    // One outline item per chapter, pointing at its first page
+    let mut entries: Vec<(usize, &str)> = Vec::new();
+    let mut first_page = usize::from(package.cover.is_some());
+    for chapter in &package.chapters {
+        if !chapter.pages.is_empty() {
+            entries.push((first_page, &chapter.label));
+        }
+        first_page += chapter.pages.len();
+    }
+    let first_entry = page_object(pages.len(), 0);
+    for (index, (page, label)) in entries.iter().enumerate() {
+        let mut item = format!(
+            "<< /Title {} /Parent {} 0 R /Dest [{} 0 R /Fit]",
+            text(label),
+            OUTLINES,
+            page_object(*page, 2)
+        );
+        if index > 0 {
+            item.push_str(&format!(" /Prev {} 0 R", first_entry + index - 1));
+        }
+        if index + 1 < entries.len() {
+            item.push_str(&format!(" /Next {} 0 R", first_entry + index + 1));
+        }
+        item.push_str(" >>");
+        pdf.object(first_entry + index, &item)?;
+    }
+    let outlines = if entries.is_empty() {
+        "<< /Type /Outlines /Count 0 >>".to_string()
+    } else {
+        format!(
+            "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+            first_entry,
+            first_entry + entries.len() - 1,
+            entries.len()
+        )
+    };
+    pdf.object(OUTLINES, &outlines)?;
+
+    let mut info = format!(
+        "<< /Title {} /Subject {} /Creator (manga-dl)",
+        text(&package.title),
+        text(&package.series)
+    );
+    if !package.authors.is_empty() {
+        info.push_str(&format!(" /Author {}", text(&package.authors.join(", "))));
+    }
+    info.push_str(" >>");
+    pdf.object(INFO, &info)?;
+
+    // Vertical strips have no direction in PDF, they read like left to right
+    let direction = match package.direction {
+        ReadingDirection::RightToLeft => "/R2L",
+        ReadingDirection::LeftToRight | ReadingDirection::Vertical => "/L2R",
+    };
+    pdf.object(
+        CATALOG,
+        &format!(
+            "<< /Type /Catalog /Pages {} 0 R /Outlines {} 0 R /PageMode /UseOutlines /Lang {} /ViewerPreferences << /Direction {} >> >>",
+            PAGES,
+            OUTLINES,
+            text(&package.language),
+            direction
+        ),
+    )?;
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{PackageFormat, PackagedChapter};
+    use image::{codecs::jpeg::JpegEncoder, RgbImage};
+
+    #[test]
+    fn text_is_utf16() {
+        assert_eq!(text("Ko"), "<FEFF004B006F>");
+    }
+
+    #[test]
+    fn jpeg_color_from_the_frame() {
+        // An Adobe marker, then a frame of 4 components after fill bytes
+        let mut cmyk = vec![0xff, 0xd8, 0xff, 0xee, 0, 14];
+        cmyk.extend(b"Adobe\0\x64\0\0\0\0\x02");
+        cmyk.extend([0xff, 0xff, 0xc0, 0, 20, 8, 0, 40, 0, 30, 4]);
+        cmyk.extend([0; 12]);
+        assert_eq!(jpeg_components(&cmyk), Some((4, true)));
+        assert_eq!(jpeg_components(&cmyk[..20]), None);
+
+        let mut rgb = Vec::new();
+        JpegEncoder::new(&mut rgb)
+            .encode_image(&RgbImage::from_pixel(30, 40, image::Rgb([200, 10, 10])))
+            .unwrap();
+        assert_eq!(jpeg_components(&rgb), Some((3, false)));
+    }
+
+    #[test]
+    fn writes_jpegs_as_they_are() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = dir.path().join("1.jpg");
+        let mut bytes = Vec::new();
+        JpegEncoder::new(&mut bytes)
+            .encode_image(&RgbImage::from_pixel(30, 40, image::Rgb([200, 10, 10])))
+            .unwrap();
+        fs::write(&jpeg, &bytes).unwrap();
+        let png = dir.path().join("2.png");
+        RgbImage::from_pixel(50, 20, image::Rgb([0, 0, 0]))
+            .save(&png)
+            .unwrap();
+
+        let package = Package {
+            id: "urn:manga-dl:komi:vol-1".to_string(),
+            series: "Komi".to_string(),
+            title: "Komi Vol. 1".to_string(),
+            volume: Some(1),
+            number: None,
+            authors: vec!["Oda Tomohito".to_string()],
+            language: "en".to_string(),
+            cover: None,
+            direction: ReadingDirection::RightToLeft,
+            chapters: vec![
+                PackagedChapter {
+                    label: "Ch. 1".to_string(),
+                    pages: vec![jpeg],
+                },
+                PackagedChapter {
+                    label: "Ch. 2".to_string(),
+                    pages: vec![png],
+                },
+            ],
+        };
+        let path = dir.path().join("Komi Vol. 1.pdf");
+        package.write(PackageFormat::Pdf, &path).unwrap();
+
+        let pdf = fs::read(&path).unwrap();
+        let contains = |needle: &[u8]| pdf.windows(needle.len()).any(|window| window == needle);
+        assert!(pdf.starts_with(b"%PDF-1.7"));
+        assert!(contains(&bytes));
+        assert!(contains(b"/MediaBox [0 0 30 40]"));
+        assert!(contains(b"/MediaBox [0 0 50 20]"));
+        assert!(contains(text("Ch. 2").as_bytes()));
+        assert!(contains(b"/Direction /R2L"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+    }
+}
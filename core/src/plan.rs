@@ -0,0 +1,164 @@
+//! What a download would do, worked out before anything is downloaded. A
+//! plan can be saved and followed later, downloading exactly the chapters
+//! it lists into the folders it lists.
+
+use crate::{
+    files::write_atomic,
+    manga::{language_code, Manga},
+    record::Record,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// A rough average size of a mangadex page, for estimating downloads.
+pub const PAGE_BYTES: u64 = 400 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub id: Uuid,
+    pub title: String,
+    /// The code of the language the chapters are translated into
+    pub language: String,
+    pub path: PathBuf,
+    pub volumes: Vec<PlannedVolume>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedVolume {
+    pub volume: Option<u32>,
+    pub path: Option<PathBuf>,
+    pub covers: usize,
+    pub chapters: Vec<PlannedChapter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedChapter {
+    pub id: Uuid,
+    pub chapter: u32,
+    pub sub_chapter: Option<u32>,
+    pub volume: Option<u32>,
+    pub title: Option<String>,
+    pub groups: Vec<String>,
+    pub pages: u32,
+    pub estimated_bytes: u64,
+    pub path: Option<PathBuf>,
+    /// Already downloaded by an earlier run, so it will be skipped
+    pub exists: bool,
+}
+
+impl Plan {
+    /// The plan for downloading the selected chapters of `manga`.
+    ///
+    /// # Errors
+    /// Fails if the record of earlier downloads cannot be read.
+    pub fn new(manga: &Manga) -> Result<Self> {
+        let record = Record::load(&manga.path)?;
+        let volumes = manga
+            .volumes
+            .iter()
+            .map(|volume| PlannedVolume {
+                volume: volume.volume,
+                path: volume.path.clone(),
+                covers: volume.covers.len(),
+                chapters: volume
+                    .chapters
+                    .iter()
+                    .map(|chapter| PlannedChapter {
+                        id: chapter.id,
+                        chapter: chapter.chapter,
+                        sub_chapter: chapter.sub_chapter,
+                        volume: chapter.volume,
+                        title: chapter.title.clone(),
+                        groups: chapter.groups.clone(),
+                        pages: chapter.pages,
+                        estimated_bytes: u64::from(chapter.pages) * PAGE_BYTES,
+                        path: chapter.path.clone(),
+                        exists: record.contains(chapter),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Self {
+            id: manga.id,
+            title: manga.title.clone(),
+            language: language_code(&manga.language),
+            path: manga.path.clone(),
+            volumes,
+        })
+    }
+
+    /// Loads a plan saved with `save`.
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or is not a plan.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read(path).with_context(|| format!("Unable to read plan {}", path.display()))?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("Unable to parse plan {}", path.display()))
+    }
+
+    /// Saves the plan as JSON.
+    ///
+    /// # Errors
+    /// Fails if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn chapters(&self) -> impl Iterator<Item = &PlannedChapter> {
+        self.volumes
+            .iter()
+            .flat_map(|volume| volume.chapters.iter())
+    }
+
+    /// The estimated size of the chapters that are not downloaded yet.
+    pub fn estimated_bytes(&self) -> u64 {
+        self.chapters()
+            .filter(|chapter| !chapter.exists)
+            .map(|chapter| chapter.estimated_bytes)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_plans_load_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan = Plan {
+            id: Uuid::nil(),
+            title: "Komi".to_string(),
+            language: "en".to_string(),
+            path: dir.path().join("Komi"),
+            volumes: vec![PlannedVolume {
+                volume: None,
+                path: None,
+                covers: 0,
+                chapters: vec![PlannedChapter {
+                    id: Uuid::nil(),
+                    chapter: 1,
+                    sub_chapter: Some(5),
+                    volume: None,
+                    title: Some("Start".to_string()),
+                    groups: Vec::new(),
+                    pages: 3,
+                    estimated_bytes: 3 * PAGE_BYTES,
+                    path: None,
+                    exists: false,
+                }],
+            }],
+        };
+        let path = dir.path().join("plan.json");
+        plan.save(&path).unwrap();
+        assert_eq!(Plan::load(&path).unwrap(), plan);
+        assert_eq!(plan.estimated_bytes(), 3 * PAGE_BYTES);
+    }
+}
@@ -0,0 +1,380 @@
+//! Optional processing of downloaded pages for e-readers. It runs over a
+//! chapter once all of its pages are saved, on the CPU, and replaces the
+//! pages with the processed ones.
+
+use crate::{files::write_atomic, manga::ReadingDirection, package::chapter_pages};
+use anyhow::{anyhow, Context, Result};
+use image::{imageops::FilterType, DynamicImage, GenericImageView, GrayImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+mod crop;
+mod spread;
+mod transcode;
+mod webtoon;
+
+pub use crop::{Crop, Margins};
+pub use spread::Spreads;
+pub use transcode::{PageFormat, Transcode};
+pub use webtoon::Webtoon;
+
+/// E-readers with a ready made profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Device {
+    KindlePaperwhite,
+    KoboLibra,
+    KoboClara,
+    /// 1264x1680, a safe size for most 7 inch readers
+    Generic,
+}
+
+/// How pages are turned into what an e-reader shows best.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Pages are scaled down to fit, never up
+    pub width: u32,
+    pub height: u32,
+    pub grayscale: bool,
+    /// Above 1 darkens the midtones, which e-ink shows too light
+    pub gamma: f32,
+    /// Gray levels to reduce pages to, e-ink shows 16
+    pub levels: Option<u8>,
+    /// The JPEG quality pages are saved with, 1 to 100
+    pub quality: u8,
+}
+
+/// Everything done to the pages of a chapter after they are downloaded, in
+/// this order.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Processing {
+    /// The order split spreads are put in, set from the manga when it is
+    /// fetched
+    pub direction: ReadingDirection,
+    pub webtoon: Webtoon,
+    pub spreads: Spreads,
+    pub crop: Option<Crop>,
+    pub profile: Option<Profile>,
+    pub transcode: Option<Transcode>,
+}
+
+/// Added to the names processed pages are written under until they replace
+/// the downloaded pages.
+const STAGED_EXTENSION: &str = "processed";
+
+/// The quality JPEG pages are saved with when no profile sets one.
+const JPEG_QUALITY: u8 = 90;
+
+const DEVICES: [(&str, Device); 4] = [
+    ("kindle-paperwhite", Device::KindlePaperwhite),
+    ("kobo-libra", Device::KoboLibra),
+    ("kobo-clara", Device::KoboClara),
+    ("generic", Device::Generic),
+];
+
+impl Device {
+    pub fn profile(self) -> Profile {
+        let (width, height) = match self {
+            Self::KindlePaperwhite => (1236, 1648),
+            Self::KoboLibra | Self::Generic => (1264, 1680),
+            Self::KoboClara => (1072, 1448),
+        };
+        Profile {
+            width,
+            height,
+            grayscale: true,
+            gamma: 1.8,
+            levels: Some(16),
+            quality: 85,
+        }
+    }
+}
+
+impl FromStr for Device {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        DEVICES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, device)| *device)
+            .ok_or_else(|| {
+                let names: Vec<&str> = DEVICES.iter().map(|(name, _)| *name).collect();
+                anyhow!("Unknown device {:?}, use one of {}", s, names.join(", "))
+            })
+    }
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = DEVICES
+            .iter()
+            .find(|(_, device)| device == self)
+            .expect("Every device has a name");
+        write!(f, "{}", name)
+    }
+}
+
+/// Maps every gray value through the gamma curve and down to `levels`.
+fn tone_curve(gamma: f32, levels: Option<u8>) -> [u8; 256] {
+    let mut curve = [0; 256];
+    for (value, mapped) in curve.iter_mut().enumerate() {
+        let mut level = (value as f32 / 255.0).powf(gamma);
+        if let Some(levels) = levels.filter(|levels| *levels > 1) {
+            let steps = f32::from(levels - 1);
+            level = (level * steps).round() / steps;
+        }
+        *mapped = (level * 255.0).round() as u8;
+    }
+    curve
+}
+
+impl Profile {
+    fn apply(&self, mut image: DynamicImage) -> DynamicImage {
+        let (width, height) = image.dimensions();
+        if width > self.width || height > self.height {
+            image = image.resize(self.width, self.height, FilterType::Lanczos3);
+        }
+        let curve = tone_curve(self.gamma, self.levels);
+        if self.grayscale {
+            let mut gray: GrayImage = image.to_luma8();
+            gray.pixels_mut()
+                .for_each(|pixel| pixel.0[0] = curve[pixel.0[0] as usize]);
+            DynamicImage::ImageLuma8(gray)
+        } else {
+            let mut rgb = image.to_rgb8();
+            rgb.pixels_mut()
+                .for_each(|pixel| pixel.0.iter_mut().for_each(|c| *c = curve[*c as usize]));
+            DynamicImage::ImageRgb8(rgb)
+        }
+    }
+}
+
+impl Processing {
+    pub fn is_enabled(&self) -> bool {
+        self.webtoon != Webtoon::Off
+            || self.spreads != Spreads::Keep
+            || self.crop.is_some()
+            || self.profile.is_some()
+            || self.transcode.is_some()
+    }
+
+    /// Processes one page into the pages replacing it, each encoded and
+    /// with its extension. Pages nothing was done to are returned as they
+    /// are.
+    fn page(&self, path: &Path) -> Result<Vec<(Vec<u8>, String)>> {
+        let image =
+            image::open(path).with_context(|| format!("Unable to read page {}", path.display()))?;
+        let (pages, split) = self
+            .spreads
+            .apply(&image, self.direction)
+            .map_or_else(|| (vec![image], false), |pages| (pages, true));
+        pages
+            .into_iter()
+            .map(|page| self.finish(page, split, path))
+            .collect()
+    }
+
+    /// Crops a page if asked to and encodes it.
+    fn finish(
+        &self,
+        mut page: DynamicImage,
+        changed: bool,
+        path: &Path,
+    ) -> Result<(Vec<u8>, String)> {
+        let mut changed = changed;
+        if let Some(crop) = &self.crop {
+            let margins = crop.margins(&page);
+            if !margins.is_empty() {
+                page = margins.apply(&page);
+                changed = true;
+            }
+        }
+        self.encode(&page, changed, path)
+    }
+
+    /// How many times taller than wide webtoon pages are sliced, the
+    /// profile's screen or else the generic one.
+    fn page_ratio(&self) -> f64 {
+        let profile = self.profile.unwrap_or_else(|| Device::Generic.profile());
+        f64::from(profile.height) / f64::from(profile.width)
+    }
+
+    /// Encodes a processed page in the transcode format, unless it is set
+    /// to `only_if_smaller` and that is not smaller than keeping the format.
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        changed: bool,
+        path: &Path,
+    ) -> Result<(Vec<u8>, String)> {
+        let applied = self.profile.map(|profile| profile.apply(image.clone()));
+        let image = applied.as_ref().unwrap_or(image);
+        let Some(transcode) = &self.transcode else {
+            return self.keep_format(image, changed, path);
+        };
+
+        let bytes = transcode.format.encode(image, transcode.quality)?;
+        if transcode.only_if_smaller {
+            let kept = self.keep_format(image, changed, path)?;
+            if kept.0.len() <= bytes.len() {
+                return Ok(kept);
+            }
+        }
+        Ok((bytes, transcode.format.extension().to_string()))
+    }
+
+    /// Encodes a page without transcoding it: in the profile's JPEG or else
+    /// the format of the page it came from.
+    fn keep_format(
+        &self,
+        image: &DynamicImage,
+        changed: bool,
+        path: &Path,
+    ) -> Result<(Vec<u8>, String)> {
+        if let Some(profile) = &self.profile {
+            let bytes = PageFormat::Jpeg.encode(image, profile.quality)?;
+            return Ok((bytes, PageFormat::Jpeg.extension().to_string()));
+        }
+
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !changed {
+            return Ok((fs::read(path)?, ext));
+        }
+        if ImageFormat::from_extension(&ext) == Some(ImageFormat::Jpeg) {
+            Ok((PageFormat::Jpeg.encode(image, JPEG_QUALITY)?, ext))
+        } else {
+            // Other formats are written back as PNG, which keeps the page lossless
+            let bytes = PageFormat::Png.encode(image, JPEG_QUALITY)?;
+            Ok((bytes, PageFormat::Png.extension().to_string()))
+        }
+    }
+
+    /// Processes every page saved in `dir` and replaces them with the
+    /// results, numbered in reading order without gaps even when spreads
+    /// were split or strips sliced. Returns the size of each page stored.
+    ///
+    /// # Errors
+    /// Fails if a page cannot be decoded, encoded or written.
+    pub fn chapter(&self, dir: &Path) -> Result<Vec<usize>> {
+        let pages = chapter_pages(dir)?;
+        let mut processed = Vec::with_capacity(pages.len());
+        if self.webtoon.applies(&pages)? {
+            for slice in webtoon::reslice(&pages, self.page_ratio())? {
+                processed.push(self.finish(DynamicImage::ImageRgb8(slice), true, &pages[0])?);
+            }
+        } else {
+            for page in &pages {
+                processed.extend(self.page(page)?);
+            }
+        }
+
+        // The processed pages are saved under other names first, so the
+        // originals are only removed once every page has been written
+        let width = processed.len().to_string().len();
+        let mut staged: Vec<(PathBuf, PathBuf)> = Vec::with_capacity(processed.len());
+        for (index, (bytes, ext)) in processed.iter().enumerate() {
+            let path = dir.join(format!("{:0width$}.{}", index + 1, ext, width = width));
+            let temporary = path.with_extension(format!("{}.{}", ext, STAGED_EXTENSION));
+            if let Err(error) = write_atomic(&temporary, bytes) {
+                for (written, _) in &staged {
+                    let _ = fs::remove_file(written);
+                }
+                return Err(error);
+            }
+            staged.push((temporary, path));
+        }
+        for page in &pages {
+            fs::remove_file(page)?;
+        }
+        for (temporary, path) in &staged {
+            fs::rename(temporary, path)?;
+        }
+        Ok(processed.iter().map(|(bytes, _)| bytes.len()).collect())
+    }
+}
+
+/// The pages in `dir` and every folder below it, by folder.
+pub(crate) fn pages_below(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut pages = chapter_pages(dir)?;
+    let mut folders: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    folders.retain(|folder| folder.is_dir());
+    folders.sort();
+    for folder in folders {
+        pages.extend(pages_below(&folder)?);
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn devices_round_trip() {
+        for (name, device) in DEVICES {
+            assert_eq!(name.parse::<Device>().unwrap(), device);
+            assert_eq!(device.to_string(), name);
+        }
+        assert!("kindle".parse::<Device>().is_err());
+    }
+
+    #[test]
+    fn tone_curve_quantises() {
+        let curve = tone_curve(1.0, Some(16));
+        assert_eq!(curve[0], 0);
+        assert_eq!(curve[255], 255);
+        assert!(curve.iter().all(|value| value % 17 == 0));
+        // Gamma above 1 darkens the midtones
+        assert!(tone_curve(1.8, None)[128] < 128);
+    }
+
+    #[test]
+    fn fits_pages_to_the_device() {
+        let profile = Profile {
+            width: 100,
+            height: 150,
+            ..Device::Generic.profile()
+        };
+        let page = DynamicImage::ImageRgb8(RgbImage::from_pixel(200, 180, Rgb([200, 10, 10])));
+        let processed = profile.apply(page);
+        assert_eq!(processed.dimensions(), (100, 90));
+        assert!(processed.as_luma8().is_some());
+
+        let small = DynamicImage::ImageRgb8(RgbImage::new(50, 60));
+        assert_eq!(profile.apply(small).dimensions(), (50, 60));
+    }
+
+    #[test]
+    fn processed_pages_replace_the_downloaded_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["1.png", "2.png"] {
+            RgbImage::from_pixel(200, 300, Rgb([200, 10, 10]))
+                .save(dir.path().join(name))
+                .unwrap();
+        }
+        let processing = Processing {
+            profile: Some(Device::Generic.profile()),
+            ..Processing::default()
+        };
+        processing.chapter(dir.path()).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["1.jpg", "2.jpg"]);
+    }
+}
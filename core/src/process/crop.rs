@@ -0,0 +1,214 @@
+use super::pages_below;
+use anyhow::{Context, Result};
+use image::{DynamicImage, GrayImage};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Crops the uniform borders off pages. The border colour is taken from the
+/// corners of each page, so both white and black borders are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crop {
+    /// How far a pixel may be from the border colour and still count as
+    /// border
+    pub tolerance: u8,
+    /// Keep a page number standing alone above or below the page instead of
+    /// cropping it off with the margin
+    pub keep_page_numbers: bool,
+}
+
+/// The pixels cropped off each side of a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Margins {
+    pub width: u32,
+    pub height: u32,
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Default for Crop {
+    fn default() -> Self {
+        Self {
+            tolerance: 24,
+            keep_page_numbers: false,
+        }
+    }
+}
+
+/// The index of the first line with content. A short band of content
+/// followed by a gap, like a page number, is skipped unless `keep_small`.
+fn first_content(lines: &[bool], keep_small: bool) -> Option<usize> {
+    let start = lines.iter().position(|line| *line)?;
+    if keep_small {
+        return Some(start);
+    }
+    let band = lines[start..].iter().take_while(|line| **line).count();
+    let gap = lines[start + band..]
+        .iter()
+        .take_while(|line| !**line)
+        .count();
+    let is_page_number = band <= (lines.len() / 25).max(1)
+        && gap >= (lines.len() / 100).max(1)
+        && start + band + gap < lines.len();
+    Some(if is_page_number {
+        start + band + gap
+    } else {
+        start
+    })
+}
+
+/// The index of the first and last line with content.
+fn content(lines: &[bool], keep_small: bool) -> Option<(usize, usize)> {
+    let first = first_content(lines, keep_small)?;
+    let reversed: Vec<bool> = lines.iter().rev().copied().collect();
+    let last = lines.len() - 1 - first_content(&reversed, keep_small)?;
+    (first <= last).then_some((first, last))
+}
+
+impl Crop {
+    /// The margins to crop off `image`, empty for blank pages.
+    pub fn margins(&self, image: &DynamicImage) -> Margins {
+        let gray: GrayImage = image.to_luma8();
+        let (width, height) = gray.dimensions();
+        let mut margins = Margins {
+            width,
+            height,
+            ..Margins::default()
+        };
+        if width == 0 || height == 0 {
+            return margins;
+        }
+
+        let mut corners = [
+            gray.get_pixel(0, 0).0[0],
+            gray.get_pixel(width - 1, 0).0[0],
+            gray.get_pixel(0, height - 1).0[0],
+            gray.get_pixel(width - 1, height - 1).0[0],
+        ];
+        corners.sort_unstable();
+        let border = corners[1];
+        let is_ink = |x: u32, y: u32| gray.get_pixel(x, y).0[0].abs_diff(border) > self.tolerance;
+
+        // Allow a little noise from the scan in a line of border
+        let rows: Vec<bool> = (0..height)
+            .map(|y| (0..width).filter(|x| is_ink(*x, y)).count() as u32 > width / 200)
+            .collect();
+        let Some((top, bottom)) = content(&rows, self.keep_page_numbers) else {
+            return margins;
+        };
+        let kept = (bottom - top + 1) as u32;
+        let columns: Vec<bool> = (0..width)
+            .map(|x| {
+                (top as u32..=bottom as u32)
+                    .filter(|y| is_ink(x, *y))
+                    .count() as u32
+                    > kept / 200
+            })
+            .collect();
+        let Some((left, right)) = content(&columns, true) else {
+            return margins;
+        };
+
+        margins.left = left as u32;
+        margins.top = top as u32;
+        margins.right = width - 1 - right as u32;
+        margins.bottom = height - 1 - bottom as u32;
+        margins
+    }
+
+    /// The margins every page in `dir`, and the folders below it, would
+    /// lose without changing any of them.
+    ///
+    /// # Errors
+    /// Fails if a folder cannot be read or a page cannot be decoded.
+    pub fn preview(&self, dir: &Path) -> Result<Vec<(PathBuf, Margins)>> {
+        pages_below(dir)?
+            .into_iter()
+            .map(|page| {
+                let image = image::open(&page)
+                    .with_context(|| format!("Unable to read page {}", page.display()))?;
+                let margins = self.margins(&image);
+                Ok((page, margins))
+            })
+            .collect()
+    }
+}
+
+impl Margins {
+    pub fn is_empty(&self) -> bool {
+        self.left == 0 && self.top == 0 && self.right == 0 && self.bottom == 0
+    }
+
+    /// The number of pixels cropped off.
+    pub fn pixels(&self) -> u64 {
+        let kept = u64::from(self.width - self.left - self.right)
+            * u64::from(self.height - self.top - self.bottom);
+        u64::from(self.width) * u64::from(self.height) - kept
+    }
+
+    pub(super) fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        image.crop_imm(
+            self.left,
+            self.top,
+            self.width - self.left - self.right,
+            self.height - self.top - self.bottom,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgb, RgbImage};
+
+    /// A white page with a black block and a page number below it.
+    fn page(background: u8, ink: u8) -> DynamicImage {
+        let mut page = GrayImage::from_pixel(200, 300, Luma([background]));
+        for (x, y, pixel) in page.enumerate_pixels_mut() {
+            let block = (20..180).contains(&x) && (30..250).contains(&y);
+            let number = (95..105).contains(&x) && (270..278).contains(&y);
+            if block || number {
+                *pixel = Luma([ink]);
+            }
+        }
+        DynamicImage::ImageLuma8(page)
+    }
+
+    #[test]
+    fn crops_white_and_black_borders() {
+        for (background, ink) in [(255, 0), (0, 255)] {
+            let margins = Crop::default().margins(&page(background, ink));
+            assert_eq!(
+                (margins.left, margins.top, margins.right, margins.bottom),
+                (20, 30, 20, 50)
+            );
+            assert_eq!(margins.apply(&page(background, ink)).width(), 160);
+        }
+    }
+
+    #[test]
+    fn keeps_page_numbers() {
+        let crop = Crop {
+            keep_page_numbers: true,
+            ..Crop::default()
+        };
+        let margins = crop.margins(&page(255, 0));
+        assert_eq!((margins.top, margins.bottom), (30, 22));
+    }
+
+    #[test]
+    fn blank_pages_are_left_alone() {
+        let blank = DynamicImage::ImageRgb8(RgbImage::from_pixel(50, 50, Rgb([250, 250, 250])));
+        let margins = Crop::default().margins(&blank);
+        assert!(margins.is_empty());
+        assert_eq!(margins.pixels(), 0);
+    }
+
+    #[test]
+    fn finds_content_lines() {
+        let lines = [false, true, true, false, false];
+        assert_eq!(content(&lines, true), Some((1, 2)));
+        assert_eq!(content(&[false; 4], true), None);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::manga::ReadingDirection;
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// Pages at least this much wider than they are tall are taken for spreads.
+const SPREAD_RATIO: f64 = 1.2;
+
+/// What to do with two-page spreads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Spreads {
+    /// Leave spreads as they are
+    #[default]
+    Keep,
+    /// Turn spreads a quarter clockwise, to read with the device on its side
+    Rotate,
+    /// Split spreads into two pages, in reading order
+    Split,
+    /// Keep the spread and follow it with the two pages split from it
+    SplitAndKeep,
+}
+
+const MODES: [(&str, Spreads); 4] = [
+    ("keep", Spreads::Keep),
+    ("rotate", Spreads::Rotate),
+    ("split", Spreads::Split),
+    ("split-and-keep", Spreads::SplitAndKeep),
+];
+
+fn is_spread(image: &DynamicImage) -> bool {
+    let (width, height) = image.dimensions();
+    f64::from(width) >= f64::from(height) * SPREAD_RATIO
+}
+
+/// The column the pages of a spread meet at: the most even column in the
+/// middle tenth of the spread, or the middle if they are all alike.
+fn gutter(image: &DynamicImage) -> u32 {
+    let gray = image.to_luma8();
+    let (width, height) = gray.dimensions();
+    let middle = width / 2;
+    let reach = width / 20;
+    let spread = |x: u32| {
+        let values: Vec<f64> = (0..height)
+            .map(|y| f64::from(gray.get_pixel(x, y).0[0]))
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / values.len() as f64
+    };
+
+    let mut best = (middle, spread(middle));
+    for offset in 1..=reach {
+        for x in [middle - offset, middle + offset] {
+            let score = spread(x);
+            // Only move away from the middle for a clearly better column
+            if score + 1.0 < best.1 {
+                best = (x, score);
+            }
+        }
+    }
+    best.0
+}
+
+impl Spreads {
+    /// The pages to replace `image` with, in reading order, `None` if it is
+    /// left as it is.
+    pub(super) fn apply(
+        self,
+        image: &DynamicImage,
+        direction: ReadingDirection,
+    ) -> Option<Vec<DynamicImage>> {
+        if self == Self::Keep || !is_spread(image) {
+            return None;
+        }
+        if self == Self::Rotate {
+            return Some(vec![image.rotate90()]);
+        }
+
+        let (width, height) = image.dimensions();
+        let gutter = gutter(image);
+        let left = image.crop_imm(0, 0, gutter, height);
+        let right = image.crop_imm(gutter, 0, width - gutter, height);
+        let mut pages = Vec::with_capacity(3);
+        if self == Self::SplitAndKeep {
+            pages.push(image.clone());
+        }
+        if direction == ReadingDirection::RightToLeft {
+            pages.extend([right, left]);
+        } else {
+            pages.extend([left, right]);
+        }
+        Some(pages)
+    }
+}
+
+impl FromStr for Spreads {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        MODES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, mode)| *mode)
+            .ok_or_else(|| {
+                let names: Vec<&str> = MODES.iter().map(|(name, _)| *name).collect();
+                anyhow!(
+                    "Unknown spread mode {:?}, use one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl Display for Spreads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = MODES
+            .iter()
+            .find(|(_, mode)| mode == self)
+            .expect("Every spread mode has a name");
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    const RTL: ReadingDirection = ReadingDirection::RightToLeft;
+
+    /// A spread with a dark right page, a light left page and a white
+    /// gutter off the middle.
+    fn spread() -> DynamicImage {
+        let mut spread = GrayImage::new(200, 100);
+        for (x, y, pixel) in spread.enumerate_pixels_mut() {
+            *pixel = match x {
+                96 => Luma([255]),
+                x if x > 96 => Luma([((x + y) % 50) as u8]),
+                _ => Luma([200 + ((x + y) % 50) as u8]),
+            };
+        }
+        DynamicImage::ImageLuma8(spread)
+    }
+
+    #[test]
+    fn splits_at_the_gutter_in_reading_order() {
+        let pages = Spreads::Split.apply(&spread(), RTL).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].dimensions(), (104, 100));
+        assert_eq!(pages[1].dimensions(), (96, 100));
+        assert!(pages[0].to_luma8().get_pixel(10, 10).0[0] < 100);
+
+        let kept = Spreads::SplitAndKeep.apply(&spread(), RTL).unwrap();
+        assert_eq!(kept[0].dimensions(), (200, 100));
+        assert_eq!(kept.len(), 3);
+
+        let ltr = Spreads::Split
+            .apply(&spread(), ReadingDirection::LeftToRight)
+            .unwrap();
+        assert_eq!(ltr[0].dimensions(), (96, 100));
+    }
+
+    #[test]
+    fn rotates_spreads_only() {
+        let pages = Spreads::Rotate.apply(&spread(), RTL).unwrap();
+        assert_eq!(pages[0].dimensions(), (100, 200));
+
+        let page = DynamicImage::ImageLuma8(GrayImage::new(100, 150));
+        assert!(Spreads::Split.apply(&page, RTL).is_none());
+        assert!(Spreads::Keep.apply(&spread(), RTL).is_none());
+    }
+
+    #[test]
+    fn modes_round_trip() {
+        for (name, mode) in MODES {
+            assert_eq!(name.parse::<Spreads>().unwrap(), mode);
+            assert_eq!(mode.to_string(), name);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+#[cfg(not(feature = "avif"))]
+use anyhow::bail;
+use anyhow::{anyhow, Result};
+#[cfg(feature = "avif")]
+use image::codecs::avif::AvifEncoder;
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    DynamicImage, ImageEncoder,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// From 1 (slowest, smallest) to 10, AVIF is slow to encode on the CPU.
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 8;
+
+/// The image formats pages can be transcoded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Avif,
+}
+
+/// Re-encodes every page into one format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcode {
+    pub format: PageFormat,
+    /// 1 to 100, ignored for PNG
+    pub quality: u8,
+    /// Keep the page as it was when transcoding does not make it smaller
+    pub only_if_smaller: bool,
+}
+
+const FORMATS: [(&str, PageFormat); 4] = [
+    ("jpeg", PageFormat::Jpeg),
+    ("png", PageFormat::Png),
+    ("webp", PageFormat::Webp),
+    ("avif", PageFormat::Avif),
+];
+
+impl PageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+        }
+    }
+
+    /// A quality that keeps pages sharp while saving most of the size.
+    pub fn default_quality(&self) -> u8 {
+        match self {
+            Self::Jpeg => 85,
+            Self::Png => 100,
+            Self::Webp => 80,
+            Self::Avif => 60,
+        }
+    }
+
+    /// Whether this build can encode the format, AVIF needs the `avif`
+    /// feature.
+    pub fn is_available(self) -> bool {
+        self != Self::Avif || cfg!(feature = "avif")
+    }
+
+    /// Encodes `image` in this format.
+    ///
+    /// # Errors
+    /// Fails if the encoder fails or the format is not available.
+    pub(super) fn encode(self, image: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
+        let quality = quality.clamp(1, 100);
+        let mut bytes = Vec::new();
+        match self {
+            Self::Jpeg => {
+                // `encode_image` would take a `DynamicImage` as RGBA, gray
+                // pages are written as they are to stay gray
+                let image = if image.color().has_color() {
+                    DynamicImage::ImageRgb8(image.to_rgb8())
+                } else {
+                    DynamicImage::ImageLuma8(image.to_luma8())
+                };
+                JpegEncoder::new_with_quality(&mut bytes, quality).write_image(
+                    image.as_bytes(),
+                    image.width(),
+                    image.height(),
+                    image.color(),
+                )?;
+            }
+            Self::Png => {
+                PngEncoder::new(&mut bytes).write_image(
+                    image.as_bytes(),
+                    image.width(),
+                    image.height(),
+                    image.color(),
+                )?;
+            }
+            Self::Webp => {
+                // The WebP encoder only takes RGB(A)
+                let image = image.to_rgba8();
+                let encoded = webp::Encoder::from_rgba(&image, image.width(), image.height())
+                    .encode(f32::from(quality));
+                bytes.extend_from_slice(&encoded);
+            }
+            #[cfg(not(feature = "avif"))]
+            Self::Avif => bail!("AVIF support is not built in, build with the avif feature"),
+            #[cfg(feature = "avif")]
+            Self::Avif => {
+                AvifEncoder::new_with_speed_quality(&mut bytes, AVIF_SPEED, quality).write_image(
+                    image.as_bytes(),
+                    image.width(),
+                    image.height(),
+                    image.color(),
+                )?;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+impl Transcode {
+    pub fn new(format: PageFormat) -> Self {
+        Self {
+            format,
+            quality: format.default_quality(),
+            only_if_smaller: false,
+        }
+    }
+}
+
+impl FromStr for PageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jpg" => Ok(Self::Jpeg),
+            _ => FORMATS
+                .iter()
+                .find(|(name, _)| *name == s)
+                .map(|(_, format)| *format)
+                .ok_or_else(|| anyhow!("Unknown page format {:?}, use jpeg, png, webp or avif", s)),
+        }
+    }
+}
+
+impl Display for PageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = FORMATS
+            .iter()
+            .find(|(_, format)| format == self)
+            .expect("Every page format has a name");
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn formats_round_trip() {
+        for (name, format) in FORMATS {
+            assert_eq!(name.parse::<PageFormat>().unwrap(), format);
+            assert_eq!(format.to_string(), name);
+        }
+        assert_eq!("jpg".parse::<PageFormat>().unwrap(), PageFormat::Jpeg);
+    }
+
+    #[test]
+    fn encodes_readable_pages() {
+        let page = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 24, Rgb([90, 120, 200])));
+        for format in [PageFormat::Jpeg, PageFormat::Png, PageFormat::Webp] {
+            let bytes = format.encode(&page, 80).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (16, 24));
+        }
+        assert_eq!(
+            PageFormat::Avif.encode(&page, 60).is_ok(),
+            PageFormat::Avif.is_available()
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Context, Result};
+use image::{imageops::FilterType, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// Pages at least this many times taller than they are wide are strips.
+const STRIP_RATIO: f64 = 3.0;
+/// How far a colour may drift along a row that still counts as uniform.
+const UNIFORM_TOLERANCE: u8 = 16;
+
+/// Whether to stitch long strip chapters and slice them into pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Webtoon {
+    /// Leave the pages as they are
+    #[default]
+    Off,
+    /// Slice mangas tagged `Long Strip` and chapters of tall strips
+    Auto,
+    /// Slice every chapter
+    On,
+}
+
+const MODES: [(&str, Webtoon); 3] = [
+    ("off", Webtoon::Off),
+    ("auto", Webtoon::Auto),
+    ("on", Webtoon::On),
+];
+
+impl Webtoon {
+    /// Whether the chapter made of `pages` is sliced.
+    ///
+    /// # Errors
+    /// Fails if the size of a page cannot be read.
+    pub(super) fn applies(self, pages: &[PathBuf]) -> Result<bool> {
+        match self {
+            Self::Off => Ok(false),
+            Self::On => Ok(!pages.is_empty()),
+            Self::Auto => {
+                let mut ratios = pages
+                    .iter()
+                    .map(|page| {
+                        let (width, height) = image::image_dimensions(page)
+                            .with_context(|| format!("Unable to read {}", page.display()))?;
+                        Ok(f64::from(height) / f64::from(width.max(1)))
+                    })
+                    .collect::<Result<Vec<f64>>>()?;
+                if ratios.is_empty() {
+                    return Ok(false);
+                }
+                ratios.sort_by(f64::total_cmp);
+                Ok(ratios[ratios.len() / 2] >= STRIP_RATIO)
+            }
+        }
+    }
+}
+
+/// Whether every pixel of `row` is close in colour to its first.
+fn is_uniform(row: &[u8]) -> bool {
+    let (first, rest) = row.split_at(3.min(row.len()));
+    rest.chunks(3).all(|pixel| {
+        pixel
+            .iter()
+            .zip(first)
+            .all(|(channel, first)| channel.abs_diff(*first) <= UNIFORM_TOLERANCE)
+    })
+}
+
+/// The row to cut a page of at most `height` rows off the top of the strip:
+/// the lowest uniform row in its bottom quarter, so panels stay whole, or
+/// `height` if there is none.
+fn cut_row(strip: &[u8], row_bytes: usize, height: usize) -> usize {
+    (height * 3 / 4..=height)
+        .rev()
+        .find(|row| is_uniform(&strip[row * row_bytes..(row + 1) * row_bytes]))
+        .filter(|row| *row > 0)
+        .unwrap_or(height)
+}
+
+/// Stitches `pages` into one strip, scaled to the width of the first, and
+/// slices it into pages `ratio` times as tall as they are wide.
+///
+/// # Errors
+/// Fails if a page cannot be decoded.
+pub(super) fn reslice(pages: &[PathBuf], ratio: f64) -> Result<Vec<RgbImage>> {
+    let mut width = None;
+    let mut strip: Vec<u8> = Vec::new();
+    let mut slices = Vec::new();
+
+    for path in pages {
+        let image =
+            image::open(path).with_context(|| format!("Unable to read page {}", path.display()))?;
+        let width = *width.get_or_insert(image.width());
+        let image = if image.width() == width {
+            image
+        } else {
+            let height = u64::from(image.height()) * u64::from(width) / u64::from(image.width());
+            image.resize_exact(width, height as u32, FilterType::Lanczos3)
+        };
+        strip.extend_from_slice(image.to_rgb8().as_raw());
+
+        // Keeping the strip shorter than two pages bounds the memory used
+        let row_bytes = width as usize * 3;
+        let height = ((f64::from(width) * ratio) as usize).max(1);
+        while row_bytes > 0 && strip.len() / row_bytes > height {
+            let cut = cut_row(&strip, row_bytes, height);
+            let rest = strip.split_off(cut * row_bytes);
+            slices.push(slice(width, strip)?);
+            strip = rest;
+        }
+    }
+    if let Some(width) = width.filter(|_| !strip.is_empty()) {
+        slices.push(slice(width, strip)?);
+    }
+    Ok(slices)
+}
+
+fn slice(width: u32, rows: Vec<u8>) -> Result<RgbImage> {
+    let height = rows.len() / (width as usize * 3);
+    RgbImage::from_raw(width, height as u32, rows).context("Slice does not fill its rows")
+}
+
+impl FromStr for Webtoon {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        MODES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, mode)| *mode)
+            .ok_or_else(|| anyhow!("Unknown webtoon mode {:?}, use off, auto or on", s))
+    }
+}
+
+impl Display for Webtoon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = MODES
+            .iter()
+            .find(|(_, mode)| mode == self)
+            .expect("Every webtoon mode has a name");
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// A strip of panels 30 rows tall with white gutters 10 rows tall.
+    fn strip(height: u32) -> RgbImage {
+        RgbImage::from_fn(10, height, |x, y| {
+            if y % 40 < 30 {
+                Rgb([(x * 20) as u8, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn cuts_in_gutters() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages: Vec<PathBuf> = (1..=3)
+            .map(|page| {
+                let path = dir.path().join(format!("{}.png", page));
+                strip(200).save(&path).unwrap();
+                path
+            })
+            .collect();
+        assert!(Webtoon::Auto.applies(&pages).unwrap());
+
+        let slices = reslice(&pages, 5.0).unwrap();
+        assert_eq!(slices.iter().map(|slice| slice.height()).sum::<u32>(), 600);
+        for slice in &slices[..slices.len() - 1] {
+            // Pages end in a gutter, at most 50 rows tall
+            assert!(slice.height() <= 50);
+            assert_eq!(
+                slice.get_pixel(5, slice.height() - 1),
+                &Rgb([255, 255, 255])
+            );
+        }
+    }
+
+    #[test]
+    fn cuts_at_the_height_without_gutters() {
+        let row = [0, 0, 0, 255, 255, 255];
+        let strip: Vec<u8> = row.repeat(20);
+        assert_eq!(cut_row(&strip, row.len(), 10), 10);
+        assert!(is_uniform(&[7, 7, 7, 9, 9, 9]));
+    }
+
+    #[test]
+    fn regular_pages_are_not_strips() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = vec![dir.path().join("1.png")];
+        RgbImage::new(100, 150).save(&pages[0]).unwrap();
+        assert!(!Webtoon::Auto.applies(&pages).unwrap());
+        assert!(Webtoon::On.applies(&pages).unwrap());
+        assert!(!Webtoon::On.applies(&[]).unwrap());
+    }
+}
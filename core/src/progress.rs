@@ -0,0 +1,159 @@
+use serde::Serialize;
+use std::{io::Write, path::PathBuf, sync::Mutex};
+use uuid::Uuid;
+
+/// Everything that happens while a `Manga` is downloading, in the order it
+/// happens. Pages are downloaded concurrently so page events of a chapter
+/// can arrive in any order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    SeriesStarted {
+        id: Uuid,
+        title: Option<String>,
+        volumes: usize,
+        chapters: usize,
+    },
+    /// Sent for every chapter that is going to be downloaded right after
+    /// `SeriesStarted`.
+    ChapterQueued {
+        id: Uuid,
+        volume: Option<u32>,
+        chapter: u32,
+        sub_chapter: Option<u32>,
+    },
+    VolumeStarted {
+        volume: Option<u32>,
+        covers: usize,
+        chapters: usize,
+    },
+    /// The manga's main cover was saved in its folder.
+    SeriesCoverDone {
+        id: Uuid,
+    },
+    /// The manga's main cover could not be saved, the download goes on
+    /// without it.
+    SeriesCoverFailed {
+        id: Uuid,
+        error: String,
+    },
+    CoverDone {
+        id: Uuid,
+        volume: Option<u32>,
+    },
+    /// The page urls are known and the pages are being downloaded.
+    ChapterStarted {
+        id: Uuid,
+        pages: usize,
+    },
+    PageDone {
+        chapter: Uuid,
+        page: usize,
+        bytes: usize,
+    },
+    PageFailed {
+        chapter: Uuid,
+        page: usize,
+        error: String,
+    },
+    ChapterDone {
+        id: Uuid,
+    },
+    /// The chapter was already downloaded by an earlier run.
+    ChapterSkipped {
+        id: Uuid,
+    },
+    ChapterFailed {
+        id: Uuid,
+        error: String,
+    },
+    VolumeDone {
+        volume: Option<u32>,
+    },
+    /// A volume, or a chapter without a volume, was packaged into `path`.
+    PackageDone {
+        path: PathBuf,
+    },
+    SeriesDone {
+        id: Uuid,
+        cancelled: bool,
+    },
+    /// An api response could not be saved in the cache, it was used all
+    /// the same.
+    CacheFailed {
+        uri: String,
+        error: String,
+    },
+}
+
+/// Receives the progress events of a download.
+pub trait ProgressSink: Send + Sync {
+    fn event(&self, event: &ProgressEvent);
+
+    /// Writes out whatever the sink still holds, for when the process is
+    /// about to exit without dropping it. Does nothing by default.
+    fn finish(&self) {}
+}
+
+/// Ignores all progress.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn event(&self, _event: &ProgressEvent) {}
+}
+
+/// Writes every event as a line of JSON, for scripting around downloads.
+#[derive(Debug)]
+pub struct JsonSink<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<W: Write + Send> ProgressSink for JsonSink<W> {
+    fn event(&self, event: &ProgressEvent) {
+        // Progress is best effort, a closed pipe shouldn't stop the download
+        if let Ok(mut writer) = self.writer.lock() {
+            if let Ok(line) = serde_json::to_string(event) {
+                let _ = writeln!(writer, "{}", line);
+                let _ = writer.flush();
+            }
+        }
+    }
+
+    fn finish(&self) {
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_lines() {
+        let sink = JsonSink::new(Vec::new());
+        sink.event(&ProgressEvent::PageDone {
+            chapter: Uuid::nil(),
+            page: 3,
+            bytes: 1024,
+        });
+        sink.event(&ProgressEvent::VolumeDone { volume: None });
+
+        let output = String::from_utf8(sink.writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "{\"event\":\"page_done\",\"chapter\":\"00000000-0000-0000-0000-000000000000\",\"page\":3,\"bytes\":1024}\n\
+             {\"event\":\"volume_done\",\"volume\":null}\n"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{credits::PageHash, files::write_atomic, manga::Chapter};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+const RECORD_FILE: &str = ".manga-dl.json";
+
+/// The chapters of a manga that have been completely downloaded, saved in
+/// the manga's folder so a later run can skip them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(skip)]
+    path: PathBuf,
+    pub chapters: BTreeMap<Uuid, RecordedChapter>,
+    /// The hashes of the credit pages found so far, by scanlation group
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub credits: BTreeMap<String, Vec<PageHash>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedChapter {
+    pub chapter: u32,
+    pub sub_chapter: Option<u32>,
+    pub volume: Option<u32>,
+    pub path: PathBuf,
+    pub pages: usize,
+}
+
+impl Record {
+    /// Loads the record of the manga saved in `dir`, a manga that has not
+    /// been downloaded before has an empty record.
+    ///
+    /// # Errors
+    /// Fails if an existing record cannot be read or parsed.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(RECORD_FILE);
+        let mut record: Record = if path.exists() {
+            let contents = fs::read(&path)
+                .with_context(|| format!("Unable to read record {}", path.display()))?;
+            serde_json::from_slice(&contents)
+                .with_context(|| format!("Unable to parse record {}", path.display()))?
+        } else {
+            Record::default()
+        };
+        record.path = path;
+        Ok(record)
+    }
+
+    /// Writes the record back to the manga's folder.
+    ///
+    /// # Errors
+    /// Fails if the record cannot be written.
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&self.path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Whether the chapter was completely downloaded to its current path.
+    pub fn contains(&self, chapter: &Chapter) -> bool {
+        self.chapters.get(&chapter.id).is_some_and(|recorded| {
+            Some(&recorded.path) == chapter.path.as_ref() && recorded.path.is_dir()
+        })
+    }
+
+    pub fn insert(&mut self, chapter: &Chapter, pages: usize) {
+        if let Some(path) = &chapter.path {
+            self.chapters.insert(
+                chapter.id,
+                RecordedChapter {
+                    chapter: chapter.chapter,
+                    sub_chapter: chapter.sub_chapter,
+                    volume: chapter.volume,
+                    path: path.clone(),
+                    pages,
+                },
+            );
+        }
+    }
+}
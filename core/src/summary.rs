@@ -0,0 +1,45 @@
+use serde::Serialize;
+use std::fmt::Display;
+use uuid::Uuid;
+
+/// What a call to `Manga::download` did.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    /// Chapters downloaded completely during this run
+    pub downloaded: Vec<Uuid>,
+    /// Chapters skipped as they were downloaded by an earlier run
+    pub skipped: Vec<Uuid>,
+    pub pages: usize,
+    /// Bytes downloaded
+    pub bytes: u64,
+    /// Bytes saved once the pages were processed
+    pub stored_bytes: u64,
+    /// Whether the download was cancelled before everything was downloaded
+    pub cancelled: bool,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Downloaded {} chapters ({} pages, {:.1} MB)",
+            self.downloaded.len(),
+            self.pages,
+            self.bytes as f64 / 1_000_000.0
+        )?;
+        if self.stored_bytes != self.bytes {
+            write!(
+                f,
+                ", stored as {:.1} MB",
+                self.stored_bytes as f64 / 1_000_000.0
+            )?;
+        }
+        if !self.skipped.is_empty() {
+            write!(f, ", skipped {} already downloaded", self.skipped.len())?;
+        }
+        if self.cancelled {
+            write!(f, ", stopped early")?;
+        }
+        Ok(())
+    }
+}
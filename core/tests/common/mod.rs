@@ -0,0 +1,443 @@
+//! A local stand-in for the mangadex api, serving the manga, chapters and
+//! covers in `tests/fixtures` along with the at-home server, the image
+//! files and an AniList api knowing one manga. Faults can be injected for
+//! any path to test error handling.
+#![allow(dead_code)]
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use manga_dl_core::{Downloader, Language, Manga, SeriesQuery, Site};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub const KOMI: &str = "a96676e5-8ae2-425e-b549-7f15dd34a6d8";
+pub const OTHER: &str = "b0b721ff-c388-4486-aa0f-c2b0bb321512";
+pub const GROUP_A: &str = "0b2a0e3d-5d8b-4a5a-9b7b-1c1c1c1c1c01";
+pub const GROUP_B: &str = "0b2a0e3d-5d8b-4a5a-9b7b-1c1c1c1c1c02";
+
+/// A 1x1 white png, served for every page and cover.
+pub const PNG: &[u8] = &[
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0x3a, 0x7e, 0x9b,
+    0x55, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0xf8, 0x0f, 0x00, 0x01,
+    0x01, 0x01, 0x00, 0x1c, 0xb0, 0x8c, 0x99, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae,
+    0x42, 0x60, 0x82,
+];
+
+#[derive(Debug, Clone)]
+pub enum Fault {
+    Status(StatusCode),
+    /// Waits this long before answering normally
+    Delay(Duration),
+}
+
+#[derive(Debug)]
+struct Rule {
+    path: String,
+    fault: Fault,
+    remaining: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    pub manga: Vec<Value>,
+    pub chapters: HashMap<String, Vec<Value>>,
+    pub covers: Vec<Value>,
+    /// The most items returned by one page of a list, whatever the limit
+    pub page_size: usize,
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        Self {
+            manga: serde_json::from_str(include_str!("../fixtures/manga.json")).unwrap(),
+            chapters: serde_json::from_str(include_str!("../fixtures/chapters.json")).unwrap(),
+            covers: serde_json::from_str(include_str!("../fixtures/covers.json")).unwrap(),
+            page_size: 100,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MockState {
+    url: String,
+    fixtures: Fixtures,
+    rules: Mutex<Vec<Rule>>,
+    requests: Mutex<Vec<String>>,
+}
+
+#[derive(Debug)]
+pub struct MockServer {
+    pub url: String,
+    state: Arc<MockState>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        Self::with_fixtures(Fixtures::default()).await
+    }
+
+    pub async fn with_fixtures(fixtures: Fixtures) -> Self {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(MockState {
+            url: url.clone(),
+            fixtures,
+            rules: Mutex::new(Vec::new()),
+            requests: Mutex::new(Vec::new()),
+        });
+
+        let app = Router::new()
+            .route("/manga", get(manga_list))
+            .route("/manga/:id", get(manga))
+            .route("/manga/:id/feed", get(feed))
+            .route("/chapter/:id", get(chapter))
+            .route("/cover", get(cover_list))
+            .route("/at-home/server/:id", get(at_home))
+            .route("/data/:hash/:file", get(image))
+            .route("/covers/:manga/:file", get(image))
+            .route("/anilist", post(anilist))
+            .layer(middleware::from_fn_with_state(state.clone(), faults))
+            .with_state(state.clone());
+
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        Self { url, state }
+    }
+
+    /// Makes the next `times` requests whose path starts with `path` fail.
+    pub fn inject(&self, path: &str, fault: Fault, times: usize) {
+        self.state.rules.lock().unwrap().push(Rule {
+            path: path.to_string(),
+            fault,
+            remaining: times,
+        });
+    }
+
+    /// How many requests were made to paths starting with `path`.
+    pub fn requests(&self, path: &str) -> usize {
+        self.state
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with(path))
+            .count()
+    }
+
+    pub fn downloader(&self) -> Downloader {
+        Downloader::builder()
+            .api_url(self.url.parse().unwrap())
+            .uploads_url(self.url.parse().unwrap())
+            .anilist_url(format!("{}/anilist", self.url).parse().unwrap())
+            .timeout(Duration::from_secs(2))
+            .build()
+            .unwrap()
+    }
+}
+
+/// The chapter and sub chapter numbers of every chapter, in order.
+pub fn chapter_numbers(manga: &Manga) -> Vec<(u32, Option<u32>)> {
+    manga
+        .chapters()
+        .map(|chapter| (chapter.chapter, chapter.sub_chapter))
+        .collect()
+}
+
+/// Looks up the manga with `id` on `site`, searching for `title` first.
+pub fn link_query(site: Site, id: &str, title: Option<&str>) -> SeriesQuery {
+    SeriesQuery::Link {
+        site,
+        id: id.to_string(),
+        title: title.map(String::from),
+        translated_language: Language::English,
+    }
+}
+
+async fn faults(
+    State(state): State<Arc<MockState>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let path = request.uri().path().to_string();
+    state.requests.lock().unwrap().push(path.clone());
+
+    let fault = {
+        let mut rules = state.rules.lock().unwrap();
+        rules
+            .iter_mut()
+            .find(|rule| rule.remaining > 0 && path.starts_with(&rule.path))
+            .map(|rule| {
+                rule.remaining -= 1;
+                rule.fault.clone()
+            })
+    };
+
+    match fault {
+        Some(Fault::Status(status)) => (
+            status,
+            Json(json!({
+                "result": "error",
+                "errors": [{
+                    "id": "00000000-0000-0000-0000-000000000000",
+                    "status": status.as_u16(),
+                    "title": "Injected fault",
+                    "detail": null,
+                }],
+            })),
+        )
+            .into_response(),
+        Some(Fault::Delay(delay)) => {
+            tokio::time::sleep(delay).await;
+            next.run(request).await
+        }
+        None => next.run(request).await,
+    }
+}
+
+/// The values of a query parameter, arrays are sent as `name[]` or as
+/// `name[0]`, `name[1]`...
+fn params<'a>(query: &'a [(String, String)], name: &str) -> Vec<&'a str> {
+    query
+        .iter()
+        .filter(|(key, _)| {
+            key == name
+                || key
+                    .strip_suffix(']')
+                    .and_then(|key| key.split_once('['))
+                    .is_some_and(|(key, index)| {
+                        key == name && index.chars().all(|c| c.is_ascii_digit())
+                    })
+        })
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+fn number(query: &[(String, String)], name: &str, default: usize) -> usize {
+    params(query, name)
+        .first()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn collection(state: &MockState, items: Vec<Value>, query: &[(String, String)]) -> Json<Value> {
+    let limit = number(query, "limit", 10).min(state.fixtures.page_size);
+    let offset = number(query, "offset", 0);
+    let total = items.len();
+    let data: Vec<Value> = items.into_iter().skip(offset).take(limit).collect();
+
+    Json(json!({
+        "result": "ok",
+        "response": "collection",
+        "data": data,
+        "limit": limit,
+        "offset": offset,
+        "total": total,
+    }))
+}
+
+fn entity(data: Value) -> Json<Value> {
+    Json(json!({
+        "result": "ok",
+        "response": "entity",
+        "data": data,
+    }))
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "result": "error",
+            "errors": [{
+                "id": "00000000-0000-0000-0000-000000000000",
+                "status": 404,
+                "title": "Not found",
+                "detail": null,
+            }],
+        })),
+    )
+        .into_response()
+}
+
+async fn manga_list(
+    State(state): State<Arc<MockState>>,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Json<Value> {
+    let title = params(&query, "title")
+        .first()
+        .map(|title| title.to_lowercase())
+        .unwrap_or_default();
+    let items = state
+        .fixtures
+        .manga
+        .iter()
+        .filter(|manga| {
+            manga["attributes"]["title"]
+                .as_object()
+                .into_iter()
+                .flat_map(|titles| titles.values())
+                .filter_map(Value::as_str)
+                .any(|candidate| candidate.to_lowercase().contains(&title))
+        })
+        .cloned()
+        .collect();
+
+    collection(&state, items, &query)
+}
+
+async fn manga(State(state): State<Arc<MockState>>, Path(id): Path<String>) -> Response {
+    match state.fixtures.manga.iter().find(|manga| manga["id"] == id) {
+        Some(manga) => entity(manga.clone()).into_response(),
+        None => not_found(),
+    }
+}
+
+async fn chapter(State(state): State<Arc<MockState>>, Path(id): Path<String>) -> Response {
+    let chapter = state
+        .fixtures
+        .chapters
+        .values()
+        .flatten()
+        .find(|chapter| chapter["id"] == id);
+
+    match chapter {
+        Some(chapter) => entity(chapter.clone()).into_response(),
+        None => not_found(),
+    }
+}
+
+/// Knows Komi by its AniList id 97852 and MyAnimeList id 1, with a
+/// romaji title that finds the other manga on mangadex first.
+async fn anilist(Json(body): Json<Value>) -> Response {
+    let variables = &body["variables"];
+    if variables["id"] == 97852 || variables["idMal"] == 1 {
+        Json(json!({
+            "data": {
+                "Media": {
+                    "title": {
+                        "english": null,
+                        "romaji": "Komi-san wa Komyushou Desu",
+                        "native": null,
+                    },
+                    "synonyms": ["Komi Can't Communicate"],
+                },
+            },
+        }))
+        .into_response()
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "errors": [{ "message": "Not Found.", "status": 404 }],
+                "data": { "Media": null },
+            })),
+        )
+            .into_response()
+    }
+}
+
+async fn feed(
+    State(state): State<Arc<MockState>>,
+    Path(id): Path<String>,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Json<Value> {
+    let languages = params(&query, "translatedLanguage");
+    let items = state
+        .fixtures
+        .chapters
+        .get(&id)
+        .into_iter()
+        .flatten()
+        .filter(|chapter| {
+            languages.is_empty()
+                || languages
+                    .iter()
+                    .any(|language| chapter["attributes"]["translatedLanguage"] == *language)
+        })
+        .cloned()
+        .collect();
+
+    collection(&state, items, &query)
+}
+
+async fn cover_list(
+    State(state): State<Arc<MockState>>,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Json<Value> {
+    let manga_ids = params(&query, "manga");
+    let locales: Vec<&str> = params(&query, "locales")
+        .into_iter()
+        .chain(params(&query, "locale"))
+        .collect();
+    let items = state
+        .fixtures
+        .covers
+        .iter()
+        .filter(|cover| {
+            manga_ids.is_empty()
+                || cover["relationships"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|rel| manga_ids.iter().any(|id| rel["id"] == *id))
+        })
+        .filter(|cover| {
+            locales.is_empty()
+                || locales
+                    .iter()
+                    .any(|locale| cover["attributes"]["locale"] == *locale)
+        })
+        .cloned()
+        .collect();
+
+    collection(&state, items, &query)
+}
+
+async fn at_home(State(state): State<Arc<MockState>>, Path(id): Path<String>) -> Response {
+    let chapter = state
+        .fixtures
+        .chapters
+        .values()
+        .flatten()
+        .find(|chapter| chapter["id"] == id);
+
+    match chapter {
+        Some(chapter) => {
+            let pages = chapter["attributes"]["pages"].as_u64().unwrap_or(0);
+            let files: Vec<String> = (1..=pages)
+                .map(|page| format!("{}-page.png", page))
+                .collect();
+            Json(json!({
+                "result": "ok",
+                "baseUrl": state.url,
+                "chapter": {
+                    "hash": id,
+                    "data": files,
+                    "dataSaver": files,
+                },
+            }))
+            .into_response()
+        }
+        None => not_found(),
+    }
+}
+
+async fn image() -> impl IntoResponse {
+    ([("content-type", "image/png")], PNG)
+}
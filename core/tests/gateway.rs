@@ -0,0 +1,171 @@
+//! Recording, replaying and caching api requests through the gateway.
+#![cfg(feature = "gateway")]
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{chapter_numbers, link_query, Fault, MockServer, KOMI};
+use manga_dl_core::{
+    Cache, CassetteMode, Downloader, Language, Manga, ProgressEvent, ProgressSink, Site,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Keeps every event, to check what was reported.
+#[derive(Debug, Default)]
+struct Events(Mutex<Vec<ProgressEvent>>);
+
+impl ProgressSink for Events {
+    fn event(&self, event: &ProgressEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[tokio::test]
+async fn replays_recorded_cassette() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = dir.path().join("komi.json");
+    let downloader = |mode| {
+        Downloader::builder()
+            .api_url(server.url.parse().unwrap())
+            .cassette(mode)
+            .build()
+            .unwrap()
+    };
+
+    let recorded = downloader(CassetteMode::Record(cassette.clone()))
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .download_covers(true)
+        .fetch()
+        .await
+        .unwrap();
+    let requests = server.requests("/");
+
+    // Every request would now fail, so the replay has to stay offline
+    server.inject("/", Fault::Status(StatusCode::INTERNAL_SERVER_ERROR), 100);
+    let replayed = downloader(CassetteMode::Replay(cassette))
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .download_covers(true)
+        .fetch()
+        .await
+        .unwrap();
+
+    assert_eq!(server.requests("/"), requests);
+    assert_eq!(chapter_numbers(&replayed), chapter_numbers(&recorded));
+    let covers = |manga: &Manga| -> Vec<String> {
+        manga
+            .volumes
+            .iter()
+            .flat_map(|volume| &volume.covers)
+            .map(|cover| cover.file_name.clone())
+            .collect()
+    };
+    assert_eq!(covers(&replayed), covers(&recorded));
+}
+
+#[tokio::test]
+async fn anilist_lookups_are_recorded_and_cached() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = dir.path().join("komi.json");
+    let builder = || {
+        Downloader::builder()
+            .api_url(server.url.parse().unwrap())
+            .anilist_url(format!("{}/anilist", server.url).parse().unwrap())
+    };
+    let query = link_query(Site::Anilist, "97852", None);
+    let komi = KOMI.parse::<Uuid>().unwrap();
+
+    let cache = Cache::new(dir.path().join("cache"), Duration::from_secs(60));
+    let cached = builder().cache(cache).build().unwrap();
+    assert_eq!(cached.resolve(&query).await.unwrap(), komi);
+    assert_eq!(cached.resolve(&query).await.unwrap(), komi);
+    assert_eq!(server.requests("/anilist"), 1);
+
+    let recorder = builder()
+        .cassette(CassetteMode::Record(cassette.clone()))
+        .build()
+        .unwrap();
+    assert_eq!(recorder.resolve(&query).await.unwrap(), komi);
+    let requests = server.requests("/");
+
+    // Every request would now fail, so the replay has to stay offline
+    server.inject("/", Fault::Status(StatusCode::INTERNAL_SERVER_ERROR), 100);
+    let player = builder()
+        .cassette(CassetteMode::Replay(cassette))
+        .build()
+        .unwrap();
+    assert_eq!(player.resolve(&query).await.unwrap(), komi);
+    assert_eq!(server.requests("/"), requests);
+}
+
+#[test]
+fn cassette_outside_a_runtime_fails() {
+    let result = Downloader::builder()
+        .cassette(CassetteMode::Replay("komi.json".into()))
+        .build();
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn cached_responses_are_reused() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::builder()
+        .api_url(server.url.parse().unwrap())
+        .uploads_url(server.url.parse().unwrap())
+        .cache(Cache::new(dir.path(), Duration::from_secs(60)))
+        .build()
+        .unwrap();
+    let fetch = || {
+        downloader
+            .series(KOMI.parse().unwrap())
+            .output("/manga")
+            .download_covers(true)
+            .fetch()
+    };
+
+    let first = fetch().await.unwrap();
+    let requests = server.requests("/");
+    let feeds = server.requests(&format!("/manga/{}/feed", KOMI));
+    let second = fetch().await.unwrap();
+
+    // Only the chapter feed is asked for again, new chapters show up
+    assert!(feeds > 0);
+    assert_eq!(server.requests("/"), requests + feeds);
+    assert_eq!(chapter_numbers(&second), chapter_numbers(&first));
+
+    Cache::new(dir.path(), Duration::from_secs(60))
+        .clear()
+        .unwrap();
+    fetch().await.unwrap();
+    assert_eq!(server.requests("/"), requests * 2 + feeds);
+}
+
+#[tokio::test]
+async fn cache_failures_are_reported() {
+    let server = MockServer::start().await;
+    // The cache cannot make its folder where a file is
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let events = Arc::new(Events::default());
+    let downloader = Downloader::builder()
+        .api_url(server.url.parse().unwrap())
+        .cache(Cache::new(file.path(), Duration::from_secs(60)))
+        .progress(events.clone())
+        .build()
+        .unwrap();
+
+    let results = downloader.search("komi", Language::English).await.unwrap();
+    assert_eq!(results.len(), 2);
+    let events = events.0.lock().unwrap();
+    assert!(matches!(
+        events.as_slice(),
+        [ProgressEvent::CacheFailed { uri, .. }] if uri.starts_with("/manga?")
+    ));
+}
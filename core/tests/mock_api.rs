@@ -0,0 +1,851 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    chapter_numbers, link_query, Fault, Fixtures, MockServer, GROUP_A, GROUP_B, KOMI, OTHER,
+};
+use manga_dl_core::{
+    CancellationToken, CoverPolicy, CreditFilter, Device, Language, Manga, NoProgress,
+    PackageFormat, PageFormat, Processing, ReadingDirection, Selection, SeriesInfo, SeriesQuery,
+    Site, Transcode, VolumeGrouping, Webtoon,
+};
+use std::{io::Read, path::Path, time::Duration};
+use uuid::Uuid;
+
+fn title_query(title: &str, anilist_id: Option<u32>) -> SeriesQuery {
+    SeriesQuery::Title {
+        title: title.to_string(),
+        anilist_id,
+        translated_language: Language::English,
+    }
+}
+
+async fn fetch(server: &MockServer, output: &Path) -> Manga {
+    server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output)
+        .fetch()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn search_takes_first_result() {
+    let server = MockServer::start().await;
+    let id = server
+        .downloader()
+        .resolve(&title_query("komi", None))
+        .await
+        .unwrap();
+    assert_eq!(id, KOMI.parse::<Uuid>().unwrap());
+}
+
+#[tokio::test]
+async fn search_matches_anilist_id() {
+    let server = MockServer::start().await;
+    let id = server
+        .downloader()
+        .resolve(&title_query("komi", Some(1)))
+        .await
+        .unwrap();
+    assert_eq!(id, OTHER.parse::<Uuid>().unwrap());
+}
+
+#[tokio::test]
+async fn search_without_results_fails() {
+    let server = MockServer::start().await;
+    let result = server
+        .downloader()
+        .resolve(&title_query("does not exist", None))
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn chapter_links_resolve_to_their_manga() {
+    let server = MockServer::start().await;
+    let id = server
+        .downloader()
+        .resolve(&SeriesQuery::Chapter(
+            "c0ffee00-0000-4000-8000-000000000002".parse().unwrap(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(id, KOMI.parse::<Uuid>().unwrap());
+}
+
+#[tokio::test]
+async fn selecting_a_chapter_id_keeps_only_it() {
+    let server = MockServer::start().await;
+    let mut manga = fetch(&server, Path::new("/manga")).await;
+    let id: Uuid = "c0ffee00-0000-4000-8000-000000000002".parse().unwrap();
+    manga.select(&Selection {
+        chapters: None,
+        volumes: None,
+        chapter_id: Some(id),
+    });
+    let ids: Vec<Uuid> = manga.chapters().map(|chapter| chapter.id).collect();
+    assert_eq!(ids, vec![id]);
+}
+
+#[tokio::test]
+async fn linked_chapters_are_fetched_whatever_their_release() {
+    let server = MockServer::start().await;
+    // The second release of chapter 2, and a French chapter
+    for id in [
+        "c0ffee00-0000-4000-8000-000000000003",
+        "c0ffee00-0000-4000-8000-000000000007",
+    ] {
+        let id: Uuid = id.parse().unwrap();
+        let manga = server
+            .downloader()
+            .series(KOMI.parse().unwrap())
+            .output("/manga")
+            .chapter(Some(id))
+            .fetch()
+            .await
+            .unwrap();
+        let ids: Vec<Uuid> = manga.chapters().map(|chapter| chapter.id).collect();
+        assert_eq!(ids, vec![id]);
+    }
+
+    let missing = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .chapter(Some(Uuid::nil()))
+        .fetch()
+        .await;
+    assert!(missing.is_err());
+}
+
+#[tokio::test]
+async fn site_ids_match_links() {
+    let server = MockServer::start().await;
+    let downloader = server.downloader();
+    assert_eq!(
+        downloader
+            .resolve(&link_query(Site::MyAnimeList, "1", Some("komi")))
+            .await
+            .unwrap(),
+        KOMI.parse::<Uuid>().unwrap()
+    );
+    assert!(downloader
+        .resolve(&link_query(Site::MyAnimeList, "2", Some("komi")))
+        .await
+        .is_err());
+    // Only AniList and MyAnimeList ids can be looked up without a title
+    let error = downloader
+        .resolve(&link_query(Site::Kitsu, "1", None))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("title to search for"));
+}
+
+#[tokio::test]
+async fn site_ids_are_matched_on_every_page_of_results() {
+    let server = MockServer::with_fixtures(Fixtures {
+        page_size: 1,
+        ..Fixtures::default()
+    })
+    .await;
+    // Komi comes first, the manga with AniList id 1 is on the second page
+    assert_eq!(
+        server
+            .downloader()
+            .resolve(&link_query(Site::Anilist, "1", Some("komi")))
+            .await
+            .unwrap(),
+        OTHER.parse::<Uuid>().unwrap()
+    );
+    assert_eq!(server.requests("/manga"), 2);
+}
+
+#[tokio::test]
+async fn anilist_titles_are_searched_for() {
+    let server = MockServer::start().await;
+    let downloader = server.downloader();
+    // The romaji title finds the other manga, the synonym finds Komi
+    assert_eq!(
+        downloader
+            .resolve(&link_query(Site::Anilist, "97852", None))
+            .await
+            .unwrap(),
+        KOMI.parse::<Uuid>().unwrap()
+    );
+    assert_eq!(
+        downloader
+            .resolve(&title_query("does not exist", Some(97852)))
+            .await
+            .unwrap(),
+        KOMI.parse::<Uuid>().unwrap()
+    );
+    assert_eq!(server.requests("/anilist"), 2);
+    assert!(downloader
+        .resolve(&link_query(Site::Anilist, "5", None))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn search_lists_every_result() {
+    let server = MockServer::start().await;
+    let results = server
+        .downloader()
+        .search("komi", Language::English)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].id, KOMI.parse::<Uuid>().unwrap());
+    assert_eq!(results[0].title, "Komi Can't Communicate");
+    assert_eq!(results[0].year, Some(2016));
+    assert_eq!(results[0].status.as_deref(), Some("ongoing"));
+    assert_eq!(
+        results[0].links.get("al").map(String::as_str),
+        Some("97852")
+    );
+
+    let result = server
+        .downloader()
+        .search_result(KOMI.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(result, results[0]);
+}
+
+#[tokio::test]
+async fn series_info_lists_selected_chapters() {
+    let server = MockServer::start().await;
+    let mut manga = fetch(&server, Path::new("/manga")).await;
+    manga.select(&Selection {
+        chapters: Some("1..=1".parse().unwrap()),
+        volumes: None,
+        chapter_id: None,
+    });
+    let info = SeriesInfo::new(&manga);
+    assert_eq!(info.title, "Komi Can't Communicate");
+    assert_eq!(info.language, "en");
+    assert_eq!(info.chapters().count(), manga.chapters().count());
+    assert_eq!(info.links.get("mal").map(String::as_str), Some("1"));
+}
+
+#[tokio::test]
+async fn fetch_chapters_paginates() {
+    let server = MockServer::with_fixtures(Fixtures {
+        page_size: 2,
+        ..Fixtures::default()
+    })
+    .await;
+    let manga = fetch(&server, Path::new("/manga")).await;
+
+    // External and French chapters are left out, the duplicate chapter 2
+    // is only kept once
+    assert_eq!(
+        chapter_numbers(&manga),
+        vec![(3, Some(5)), (1, None), (2, None), (3, None)]
+    );
+    assert_eq!(server.requests(&format!("/manga/{}/feed", KOMI)), 3);
+}
+
+#[tokio::test]
+async fn chapters_are_grouped_into_volumes() {
+    let server = MockServer::start().await;
+    let manga = fetch(&server, Path::new("/manga/{title}")).await;
+
+    let volumes: Vec<Option<u32>> = manga.volumes.iter().map(|volume| volume.volume).collect();
+    assert_eq!(volumes, vec![None, Some(1), Some(2)]);
+    assert_eq!(
+        manga.volumes[1].chapters[0].path.as_deref(),
+        Some(Path::new(
+            "/manga/Komi Can't Communicate/Vol. 1/Ch. 1 - Chapter 1"
+        ))
+    );
+}
+
+#[tokio::test]
+async fn chapters_without_a_volume_are_grouped() {
+    let server = MockServer::start().await;
+    let manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .volume_grouping(VolumeGrouping::Every(10))
+        .fetch()
+        .await
+        .unwrap();
+
+    // Named after the bounds of the group, not the chapters out so far
+    assert_eq!(
+        manga.volumes[0].path.as_deref(),
+        Some(Path::new("/manga/Ch. 1-10"))
+    );
+    assert!(manga.volumes[0].chapters[0]
+        .path
+        .as_deref()
+        .unwrap()
+        .starts_with("/manga/Ch. 1-10"));
+}
+
+#[tokio::test]
+async fn duplicates_keep_first_release() {
+    let server = MockServer::start().await;
+    let manga = fetch(&server, Path::new("/manga")).await;
+
+    let chapter = manga
+        .chapters()
+        .find(|chapter| chapter.chapter == 2)
+        .unwrap();
+    assert_eq!(chapter.groups, vec![GROUP_A]);
+}
+
+#[tokio::test]
+async fn duplicates_prefer_groups() {
+    let server = MockServer::start().await;
+    let manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .preferred_groups(vec![GROUP_B.to_string()])
+        .fetch()
+        .await
+        .unwrap();
+
+    let chapter = manga
+        .chapters()
+        .find(|chapter| chapter.chapter == 2)
+        .unwrap();
+    assert_eq!(chapter.groups, vec![GROUP_B]);
+}
+
+#[tokio::test]
+async fn covers_are_mapped_to_volumes() {
+    let server = MockServer::start().await;
+    let manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .download_covers(true)
+        .fetch()
+        .await
+        .unwrap();
+
+    for volume in &manga.volumes {
+        let covers: Vec<Option<u32>> = volume.covers.iter().map(|cover| cover.volume).collect();
+        match volume.volume {
+            None => assert!(covers.is_empty()),
+            Some(number) => assert_eq!(covers, vec![Some(number)]),
+        }
+    }
+}
+
+#[tokio::test]
+async fn covers_fall_back_through_locales() {
+    let server = MockServer::start().await;
+    let mut manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .download_covers(true)
+        .cover_locales("en,ja,any".parse().unwrap())
+        .cover_policy(CoverPolicy::Newest)
+        .all_volume_covers(true)
+        .fetch()
+        .await
+        .unwrap();
+    manga.select(&Selection {
+        chapters: None,
+        volumes: Some("1".parse().unwrap()),
+        chapter_id: None,
+    });
+
+    // Volume 2 is not selected and volume 3 has no chapters, but both are
+    // kept for their covers
+    let covers: Vec<(Option<u32>, usize, Vec<&str>)> = manga
+        .volumes
+        .iter()
+        .map(|volume| {
+            (
+                volume.volume,
+                volume.chapters.len(),
+                volume
+                    .covers
+                    .iter()
+                    .map(|cover| cover.file_name.as_str())
+                    .collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        covers,
+        vec![
+            (Some(1), 2, vec!["cover-1-ja-4.png"]),
+            (Some(2), 0, vec!["cover-2-en.png"]),
+            (Some(3), 0, vec!["cover-3-ko-5.png"]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn downloads_pages_and_covers() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let mut manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output.path())
+        .download_covers(true)
+        .fetch()
+        .await
+        .unwrap();
+    manga.select(&Selection {
+        chapters: Some("1".parse().unwrap()),
+        volumes: None,
+        chapter_id: None,
+    });
+
+    let summary = manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(summary.downloaded.len(), 1);
+    assert_eq!(summary.pages, 2);
+
+    let chapter = output.path().join("Vol. 1/Ch. 1 - Chapter 1");
+    for page in ["1.png", "2.png"] {
+        assert_eq!(std::fs::read(chapter.join(page)).unwrap(), common::PNG);
+    }
+    assert!(output.path().join("Vol. 1/cover.png").exists());
+    assert!(output.path().join(".manga-dl.json").exists());
+
+    // A second run skips the recorded chapter
+    let summary = manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+    assert!(summary.downloaded.is_empty());
+    assert_eq!(summary.skipped.len(), 1);
+}
+
+#[tokio::test]
+async fn transcodes_pages_and_reports_sizes() {
+    let server = MockServer::start().await;
+    for (only_if_smaller, page) in [(false, "1.jpg"), (true, "1.png")] {
+        let output = tempfile::tempdir().unwrap();
+        let mut manga = server
+            .downloader()
+            .series(KOMI.parse().unwrap())
+            .output(output.path())
+            .processing(Processing {
+                transcode: Some(Transcode {
+                    only_if_smaller,
+                    ..Transcode::new(PageFormat::Jpeg)
+                }),
+                ..Processing::default()
+            })
+            .fetch()
+            .await
+            .unwrap();
+        manga.select(&Selection {
+            chapters: Some("1".parse().unwrap()),
+            volumes: None,
+            chapter_id: None,
+        });
+        let summary = manga
+            .download(&NoProgress, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        // A JPEG of the tiny page is larger than the PNG
+        assert!(output
+            .path()
+            .join("Vol. 1/Ch. 1 - Chapter 1")
+            .join(page)
+            .is_file());
+        assert_eq!(summary.bytes, 2 * common::PNG.len() as u64);
+        assert_eq!(summary.stored_bytes > summary.bytes, !only_if_smaller);
+    }
+}
+
+#[tokio::test]
+async fn long_strip_tag_turns_on_webtoon_mode() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let processing = Processing {
+        webtoon: Webtoon::Auto,
+        ..Processing::default()
+    };
+    let manga = server
+        .downloader()
+        .series(OTHER.parse().unwrap())
+        .output(output.path())
+        .processing(processing)
+        .fetch()
+        .await
+        .unwrap();
+    assert!(manga.metadata.long_strip);
+    assert_eq!(manga.metadata.reading_direction, ReadingDirection::Vertical);
+    assert_eq!(manga.processing.webtoon, Webtoon::On);
+}
+
+#[tokio::test]
+async fn processes_pages_for_a_device() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let mut manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output.path())
+        .processing(Processing {
+            profile: Some(Device::KoboClara.profile()),
+            ..Processing::default()
+        })
+        .fetch()
+        .await
+        .unwrap();
+    manga.select(&Selection {
+        chapters: Some("1".parse().unwrap()),
+        volumes: None,
+        chapter_id: None,
+    });
+    manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    let chapter = output.path().join("Vol. 1/Ch. 1 - Chapter 1");
+    assert!(!chapter.join("1.png").exists());
+    for page in ["1.jpg", "2.jpg"] {
+        let image = image::open(chapter.join(page)).unwrap();
+        assert!(image.as_luma8().is_some());
+    }
+}
+
+#[tokio::test]
+async fn packages_volumes_and_loose_chapters() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let mut manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output.path())
+        .download_covers(true)
+        .cover_thumbnails(true)
+        .fetch()
+        .await
+        .unwrap();
+    manga.select(&Selection {
+        chapters: Some("1..=3".parse().unwrap()),
+        volumes: None,
+        chapter_id: None,
+    });
+    manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    for file in [
+        "cover.png",
+        "poster.png",
+        "thumbnail-256.jpg",
+        "thumbnail-512.jpg",
+    ] {
+        assert!(output.path().join(file).is_file(), "{} is missing", file);
+    }
+
+    let packages = manga
+        .package(
+            &[
+                PackageFormat::Cbz,
+                PackageFormat::Epub,
+                PackageFormat::Azw3,
+                PackageFormat::Kepub,
+                PackageFormat::Pdf,
+            ],
+            &NoProgress,
+        )
+        .unwrap();
+    let names: Vec<String> = packages
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "Komi Can't Communicate Ch. 3.5.cbz",
+            "Komi Can't Communicate Ch. 3.5.epub",
+            "Komi Can't Communicate Ch. 3.5.azw3",
+            "Komi Can't Communicate Ch. 3.5.kepub.epub",
+            "Komi Can't Communicate Ch. 3.5.pdf",
+            "Komi Can't Communicate Vol. 1.cbz",
+            "Komi Can't Communicate Vol. 1.epub",
+            "Komi Can't Communicate Vol. 1.azw3",
+            "Komi Can't Communicate Vol. 1.kepub.epub",
+            "Komi Can't Communicate Vol. 1.pdf",
+            "Komi Can't Communicate Vol. 2.cbz",
+            "Komi Can't Communicate Vol. 2.epub",
+            "Komi Can't Communicate Vol. 2.azw3",
+            "Komi Can't Communicate Vol. 2.kepub.epub",
+            "Komi Can't Communicate Vol. 2.pdf",
+        ]
+    );
+    let azw3 = std::fs::read(&packages[7]).unwrap();
+    assert_eq!(&azw3[60..68], b"BOOKMOBI");
+    let kepub = std::fs::File::open(&packages[8]).unwrap();
+    let mut kepub = zip::ZipArchive::new(kepub).unwrap();
+    let mut opf = String::new();
+    kepub
+        .by_name("OEBPS/content.opf")
+        .unwrap()
+        .read_to_string(&mut opf)
+        .unwrap();
+    assert!(opf.contains(r#"<meta name="calibre:series_index" content="1"/>"#));
+    let mut page = String::new();
+    kepub
+        .by_name("OEBPS/pages/p0001.xhtml")
+        .unwrap()
+        .read_to_string(&mut page)
+        .unwrap();
+    assert!(page.contains(r#"<span class="koboSpan" id="kobo.1.1">"#));
+    let pdf = std::fs::read(&packages[9]).unwrap();
+    assert!(pdf.starts_with(b"%PDF-1.7"));
+    // Volume 1 has two chapters of two pages each, and its cover
+    let cbz = std::fs::File::open(&packages[5]).unwrap();
+    let mut cbz = zip::ZipArchive::new(cbz).unwrap();
+    assert_eq!(cbz.len(), 6);
+    let mut info = String::new();
+    cbz.by_name("ComicInfo.xml")
+        .unwrap()
+        .read_to_string(&mut info)
+        .unwrap();
+    assert!(info.contains("<Manga>YesAndRightToLeft</Manga>"));
+}
+
+#[tokio::test]
+async fn chapters_of_volumes_are_packaged_on_their_own() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let mut manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output.path())
+        .package_chapters(true)
+        .fetch()
+        .await
+        .unwrap();
+    manga.select(&Selection {
+        chapters: Some("1..=2".parse().unwrap()),
+        volumes: None,
+        chapter_id: None,
+    });
+    manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    let packages = manga
+        .package(&[PackageFormat::Cbz, PackageFormat::Pdf], &NoProgress)
+        .unwrap();
+    let names: Vec<String> = packages
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "Komi Can't Communicate Ch. 1.cbz",
+            "Komi Can't Communicate Ch. 1.pdf",
+            "Komi Can't Communicate Ch. 2.cbz",
+            "Komi Can't Communicate Ch. 2.pdf",
+        ]
+    );
+    // The chapter keeps its volume
+    let cbz = std::fs::File::open(&packages[0]).unwrap();
+    let mut info = String::new();
+    zip::ZipArchive::new(cbz)
+        .unwrap()
+        .by_name("ComicInfo.xml")
+        .unwrap()
+        .read_to_string(&mut info)
+        .unwrap();
+    assert!(info.contains("<Volume>1</Volume>"));
+}
+
+#[tokio::test]
+async fn credit_pages_are_left_out_and_deleted() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let mut manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output.path())
+        .credit_filter(Some(CreditFilter::new(Vec::new(), true)))
+        .fetch()
+        .await
+        .unwrap();
+    manga.select(&Selection {
+        chapters: Some("1..=2".parse().unwrap()),
+        volumes: None,
+        chapter_id: None,
+    });
+    manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+    // Blank pages are never credits
+    assert!(manga.credit_pages().unwrap().is_empty());
+
+    // Both chapters of volume 1 are by the same group, end them with the
+    // same credit page
+    let credit =
+        image::GrayImage::from_fn(60, 90, |x, y| image::Luma([((x / 6 + y / 9) * 40) as u8]));
+    let credits: Vec<_> = manga
+        .chapters()
+        .map(|chapter| chapter.path.as_ref().unwrap().join("2.png"))
+        .collect();
+    for page in &credits {
+        credit.save(page).unwrap();
+    }
+    assert_eq!(manga.credit_pages().unwrap(), credits);
+    let record = output.path().join(".manga-dl.json");
+    assert!(std::fs::read_to_string(&record).unwrap().contains(GROUP_A));
+    // Nothing new is found after that, so the record is left alone
+    std::fs::remove_file(&record).unwrap();
+
+    let packages = manga.package(&[PackageFormat::Cbz], &NoProgress).unwrap();
+    let cbz = std::fs::File::open(&packages[0]).unwrap();
+    // Two pages and ComicInfo.xml
+    assert_eq!(zip::ZipArchive::new(cbz).unwrap().len(), 3);
+
+    assert_eq!(manga.remove_credits().unwrap(), credits);
+    assert!(credits.iter().all(|page| !page.exists()));
+    assert!(!record.exists());
+}
+
+#[tokio::test]
+async fn reorganize_moves_chapters_to_their_new_folder() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let manga = fetch(&server, output.path()).await;
+    manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+    let cbz = output.path().join("Komi Can't Communicate Ch. 3.5.cbz");
+    manga.package(&[PackageFormat::Cbz], &NoProgress).unwrap();
+    assert!(cbz.is_file());
+    assert!(manga.moves().unwrap().is_empty());
+
+    // Chapter 3.5 has no volume, grouping it on its own gives it a new folder
+    let regrouped = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output.path())
+        .volume_grouping(VolumeGrouping::Single)
+        .fetch()
+        .await
+        .unwrap();
+    let moves = regrouped.moves().unwrap();
+    assert_eq!(moves.len(), 1);
+    assert!(moves[0].from.starts_with(output.path().join("Vol. None")));
+    assert_eq!(moves[0].to.parent(), Some(output.path()));
+
+    regrouped.reorganize(&moves).unwrap();
+    assert!(moves[0].to.is_dir());
+    assert!(!output.path().join("Vol. None").exists());
+    assert!(regrouped.moves().unwrap().is_empty());
+    let packages = regrouped
+        .repackage(&[PackageFormat::Cbz], &moves, &NoProgress)
+        .unwrap();
+    assert_eq!(packages, vec![cbz]);
+
+    // Nothing is downloaded again
+    let summary = regrouped
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+    assert!(summary.downloaded.is_empty());
+}
+
+#[tokio::test]
+async fn failed_series_cover_does_not_stop_the_download() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let manga = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output(output.path())
+        .download_covers(true)
+        .cover_thumbnails(true)
+        .fetch()
+        .await
+        .unwrap();
+    let thumbnail = format!("/covers/{}/cover-1-ja-4.png.256.jpg", KOMI);
+    server.inject(
+        &thumbnail,
+        Fault::Status(StatusCode::INTERNAL_SERVER_ERROR),
+        10,
+    );
+
+    let summary = manga
+        .download(&NoProgress, &CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(summary.downloaded.len(), manga.chapters().count());
+    assert!(output.path().join("cover.png").is_file());
+    assert!(!output.path().join("thumbnail-256.jpg").exists());
+}
+
+#[tokio::test]
+async fn cancelled_download_stops() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let manga = fetch(&server, output.path()).await;
+
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let summary = manga.download(&NoProgress, &cancel).await.unwrap();
+    assert!(summary.cancelled);
+    assert!(summary.downloaded.is_empty());
+    assert_eq!(server.requests("/at-home"), 0);
+}
+
+#[tokio::test]
+async fn server_errors_fail_the_download() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let manga = fetch(&server, output.path()).await;
+
+    server.inject(
+        "/at-home",
+        Fault::Status(StatusCode::INTERNAL_SERVER_ERROR),
+        1,
+    );
+    let result = manga.download(&NoProgress, &CancellationToken::new()).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn rate_limited_pages_fail_the_download() {
+    let server = MockServer::start().await;
+    let output = tempfile::tempdir().unwrap();
+    let manga = fetch(&server, output.path()).await;
+
+    server.inject("/data/", Fault::Status(StatusCode::TOO_MANY_REQUESTS), 1);
+    let result = manga.download(&NoProgress, &CancellationToken::new()).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn slow_responses_time_out() {
+    let server = MockServer::start().await;
+    server.inject("/manga/", Fault::Delay(Duration::from_secs(5)), 1);
+
+    let result = server
+        .downloader()
+        .series(KOMI.parse().unwrap())
+        .output("/manga")
+        .fetch()
+        .await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,815 @@
+use crate::{
+    config::{Config, Settings},
+    output::{self, OutputFormat},
+    plan,
+    progress::{ProgressFormat, Warnings},
+};
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use manga_dl_core::{
+    int_range::IntRange, Cache, CassetteMode, CoverLocales, CoverPolicy, Device, Downloader,
+    Identifier, Language, Manga, NoProgress, PackageFormat, PageFormat, PageHash, Plan,
+    ProgressSink, ReadingDirection, Selection, SeriesInfo, SeriesQuery, Site, Spreads,
+    VolumeGrouping, Webtoon,
+};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct GetManga {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Anilist ID, to check the result of searching for the title, or to
+    /// look the manga's titles up on `AniList` without one
+    #[arg(long)]
+    pub anilist_id: Option<u32>,
+
+    /// The `AniList` `GraphQL` api, defaults to `https://graphql.anilist.co`
+    #[arg(long, value_name = "URL")]
+    pub anilist_url: Option<String>,
+
+    /// Range of chapters to download
+    #[arg(short, long)]
+    pub chapters: Option<IntRange>,
+
+    /// Range of volumes to download
+    #[arg(short, long)]
+    pub volumes: Option<IntRange>,
+
+    /// Path to the config file, defaults to `manga-dl/config.toml` in the
+    /// user config directory
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// The locales to look for covers in, in order, e.g. `en,ja,any`.
+    /// Defaults to `ja,any`
+    #[arg(long, value_name = "LOCALES")]
+    pub cover_language: Option<CoverLocales>,
+
+    /// Which covers to download when a volume has several: first, newest
+    /// or all
+    #[arg(long)]
+    pub cover_policy: Option<CoverPolicy>,
+
+    /// Also download the covers of volumes without chapters to download
+    #[arg(long, overrides_with = "no_all_volume_covers")]
+    pub all_volume_covers: bool,
+
+    #[arg(long, overrides_with = "all_volume_covers", hide = true)]
+    pub no_all_volume_covers: bool,
+
+    /// Also save thumbnails of the main cover in the output folder
+    #[arg(long, overrides_with = "no_cover_thumbnails")]
+    pub cover_thumbnails: bool,
+
+    #[arg(long, overrides_with = "cover_thumbnails", hide = true)]
+    pub no_cover_thumbnails: bool,
+
+    /// A mangadex title or chapter link, a UUID, or `anilist:`, `mal:`,
+    /// `kitsu:` or `mu:` and the manga's id on that site
+    pub identifier: Option<String>,
+
+    /// The manga `identifier` points at, set by `from_args`
+    #[arg(skip)]
+    pub target: Option<Identifier>,
+
+    /// The UUID of the mangadex manga
+    #[arg(short, long)]
+    pub id: Option<Uuid>,
+
+    // The base file path of where the files should be saved, required if the
+    // config does not set one
+    pub output: Option<PathBuf>,
+
+    /// The title of the manga, required if id is not supplied
+    #[arg(short, long)]
+    pub title: Option<String>,
+
+    /// The language we get the manga translated into, defaults to en
+    #[arg(long)]
+    pub translated_language: Option<Language>,
+
+    /// Scanlation groups to prefer when a chapter has several releases
+    #[arg(long, value_delimiter = ',')]
+    pub preferred_groups: Vec<String>,
+
+    #[arg(long, overrides_with = "no_download_covers")]
+    pub download_covers: bool,
+
+    #[arg(long, overrides_with = "download_covers", hide = true)]
+    pub no_download_covers: bool,
+
+    /// Crop the uniform white or black margins off every downloaded page
+    #[arg(long, overrides_with = "no_crop")]
+    pub crop: bool,
+
+    #[arg(long, overrides_with = "crop", hide = true)]
+    pub no_crop: bool,
+
+    /// How far a pixel may be from the margin colour (0-255) and still be
+    /// cropped, defaults to 24
+    #[arg(long, value_name = "TOLERANCE")]
+    pub crop_tolerance: Option<u8>,
+
+    /// Keep page numbers below or above the page when cropping
+    #[arg(long, overrides_with = "no_keep_page_numbers")]
+    pub keep_page_numbers: bool,
+
+    #[arg(long, overrides_with = "keep_page_numbers", hide = true)]
+    pub no_keep_page_numbers: bool,
+
+    /// The direction the manga is read in: rtl, ltr or vertical. Defaults
+    /// to rtl for Japanese mangas, vertical for long strips and ltr for the
+    /// rest
+    #[arg(long, value_name = "DIRECTION")]
+    pub reading_direction: Option<ReadingDirection>,
+
+    /// Stitch long strip chapters and slice them into pages the height of
+    /// the device: off, auto (for mangas tagged Long Strip or chapters of
+    /// tall strips) or on
+    #[arg(long, value_name = "MODE")]
+    pub webtoon: Option<Webtoon>,
+
+    /// What to do with two-page spreads: keep, rotate, split (in reading
+    /// order) or split-and-keep
+    #[arg(long, value_name = "MODE")]
+    pub spreads: Option<Spreads>,
+
+    /// Resize, gray and requantise pages for an e-reader: kindle-paperwhite,
+    /// kobo-libra, kobo-clara or generic
+    #[arg(long)]
+    pub device: Option<Device>,
+
+    /// Transcode every page to jpeg, png, webp or avif
+    #[arg(long, value_name = "FORMAT")]
+    pub transcode: Option<PageFormat>,
+
+    /// The quality pages are transcoded with, defaults to 85 for jpeg, 80
+    /// for webp and 60 for avif
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub transcode_quality: Option<u8>,
+
+    /// Keep pages as they are when transcoding does not make them smaller
+    #[arg(long, overrides_with = "no_only_if_smaller")]
+    pub only_if_smaller: bool,
+
+    #[arg(long, overrides_with = "only_if_smaller", hide = true)]
+    pub no_only_if_smaller: bool,
+
+    /// The JPEG quality processed pages are saved with
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub jpeg_quality: Option<u8>,
+
+    /// Leave the credit and recruitment pages scanlation groups repeat
+    /// across their chapters out of packages
+    #[arg(long, overrides_with = "no_skip_credits")]
+    pub skip_credits: bool,
+
+    #[arg(long, overrides_with = "skip_credits", hide = true)]
+    pub no_skip_credits: bool,
+
+    /// Delete credit pages from disk as well as leaving them out of
+    /// packages
+    #[arg(long, overrides_with = "no_delete_credits")]
+    pub delete_credits: bool,
+
+    #[arg(long, overrides_with = "delete_credits", hide = true)]
+    pub no_delete_credits: bool,
+
+    /// Package every downloaded volume into these formats: cbz, epub, azw3, kepub,
+    /// pdf
+    #[arg(long, value_delimiter = ',')]
+    pub package: Vec<PackageFormat>,
+
+    /// Package every chapter on its own, the chapters of volumes too
+    #[arg(long, overrides_with = "no_package_chapters")]
+    pub package_chapters: bool,
+
+    #[arg(long, overrides_with = "package_chapters", hide = true)]
+    pub no_package_chapters: bool,
+
+    /// How to group chapters without a volume: keep (in Vol. None), covers
+    /// (into the volumes mangadex has covers for), single (each on its own)
+    /// or a number of chapters per volume
+    #[arg(long, value_name = "GROUPING")]
+    pub volume_grouping: Option<VolumeGrouping>,
+
+    /// Print what would be downloaded and stop, without downloading
+    /// anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// How `search`, `info` and `--dry-run` print what they found
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+
+    /// Save the plan of a `--dry-run` to this file, for `download --plan`
+    #[arg(long, value_name = "FILE", requires = "dry_run")]
+    pub save_plan: Option<PathBuf>,
+
+    /// How to report progress, `json` prints one event per line
+    #[arg(long, value_enum, default_value_t)]
+    pub progress: ProgressFormat,
+
+    /// Seconds api responses are cached for before asking mangadex again
+    #[arg(long, value_name = "SECONDS")]
+    pub cache_ttl: Option<u64>,
+
+    /// Do not use or update the api response cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Save every api request and response made into this cassette file
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer api requests from a recorded cassette instead of mangadex
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    #[arg(long, overrides_with = "no_verbose")]
+    pub verbose: bool,
+
+    #[arg(long, overrides_with = "verbose", hide = true)]
+    pub no_verbose: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Inspect the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Manage the api response cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Report how many pixels cropping would take off every page in a
+    /// folder and the folders below it, without changing anything
+    CropPreview {
+        /// A manga, volume or chapter folder
+        path: PathBuf,
+    },
+    /// Print the hash of pages, to add to the `credit_blocklist` of the
+    /// config
+    CreditHash {
+        #[arg(required = true)]
+        pages: Vec<PathBuf>,
+    },
+    /// Search mangadex for `--title` and list the mangas found, or without
+    /// a title list the manga `--anilist-id` or the identifier points at
+    Search,
+    /// Print the details of the manga and its chapters, narrowed down by
+    /// `--chapters` and `--volumes`
+    Info,
+    /// Download the chapters of a plan saved with `--dry-run --save-plan`,
+    /// into the folders it lists
+    Download {
+        #[arg(long, value_name = "FILE")]
+        plan: PathBuf,
+    },
+    /// Move downloaded chapters into the folders mangadex's current volume
+    /// numbers give them and rebuild the packages that changed
+    Reorganize {
+        /// Only print the moves, without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the effective settings after merging the config file, the
+    /// series overrides for `--id` and the command line flags
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Remove every cached api response
+    Clear,
+}
+
+/// Whether `--flag` or `--no-flag` was given, to override the config.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    (on || off).then_some(on)
+}
+
+/// Overrides a config setting with a flag, if the flag was given.
+fn set<T>(setting: &mut T, flag: Option<T>) {
+    if let Some(value) = flag {
+        *setting = value;
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+/// Prints the hash of every page in `pages`, blank pages have none.
+///
+/// Where commands other than downloads report problems, on stderr so
+/// their output stays apart.
+fn warnings() -> Arc<dyn ProgressSink> {
+    Arc::new(Warnings)
+}
+
+/// # Errors
+/// Fails if a page cannot be decoded.
+pub fn credit_hash(pages: &[PathBuf]) -> Result<()> {
+    for page in pages {
+        match PageHash::of(page)? {
+            Some(hash) => println!("{}  {}", hash, page.display()),
+            None => println!("blank             {}", page.display()),
+        }
+    }
+    Ok(())
+}
+
+impl GetManga {
+    /// Parses the command line. A lone argument that is not an identifier
+    /// is the output folder when `--id` or `--title` is given, as it was
+    /// before identifiers could be given.
+    ///
+    /// # Errors
+    /// Fails if the identifier cannot be parsed, or the manga is given both
+    /// as an identifier and with `--id`.
+    pub fn from_args() -> Result<Self> {
+        let mut manga = Self::parse();
+        if let Some(argument) = manga.identifier.clone() {
+            match argument.parse::<Identifier>() {
+                Ok(_) if manga.id.is_some() => {
+                    bail!("Give the manga either as {} or with --id", argument)
+                }
+                Ok(target) => manga.target = Some(target),
+                Err(_)
+                    if manga.output.is_none() && (manga.id.is_some() || manga.title.is_some()) =>
+                {
+                    manga.identifier = None;
+                    manga.output = Some(argument.into());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(manga)
+    }
+
+    /// The UUID of the manga when it is known without asking mangadex.
+    pub fn series_id(&self) -> Option<Uuid> {
+        match self.target {
+            Some(Identifier::Series(id)) => Some(id),
+            _ => self.id,
+        }
+    }
+
+    /// Merges the config file (and the overrides for the series `id` if
+    /// given) with the command line flags. Flags always win.
+    pub fn settings(&self, config: &Config, id: Option<&Uuid>) -> Settings {
+        let mut settings = config.settings(id);
+
+        set(&mut settings.cover_language, self.cover_language.clone());
+        set(&mut settings.cover_policy, self.cover_policy);
+        set(&mut settings.translated_language, self.translated_language);
+        set(&mut settings.device, self.device.map(Some));
+        set(&mut settings.jpeg_quality, self.jpeg_quality.map(Some));
+        set(&mut settings.transcode, self.transcode.map(Some));
+        set(
+            &mut settings.transcode_quality,
+            self.transcode_quality.map(Some),
+        );
+        set(
+            &mut settings.reading_direction,
+            self.reading_direction.map(Some),
+        );
+        set(&mut settings.webtoon, self.webtoon);
+        set(&mut settings.volume_grouping, self.volume_grouping);
+        set(&mut settings.spreads, self.spreads);
+        set(&mut settings.crop_tolerance, self.crop_tolerance);
+        set(&mut settings.cache_ttl, self.cache_ttl);
+        set(
+            &mut settings.anilist_url,
+            self.anilist_url.clone().map(Some),
+        );
+        set(&mut settings.output, self.output.clone().map(Some));
+        if !self.preferred_groups.is_empty() {
+            settings.preferred_groups = self.preferred_groups.clone();
+        }
+        if !self.package.is_empty() {
+            settings.package = self.package.clone();
+        }
+        set(
+            &mut settings.all_volume_covers,
+            flag(self.all_volume_covers, self.no_all_volume_covers),
+        );
+        set(
+            &mut settings.cover_thumbnails,
+            flag(self.cover_thumbnails, self.no_cover_thumbnails),
+        );
+        set(&mut settings.crop, flag(self.crop, self.no_crop));
+        set(
+            &mut settings.delete_credits,
+            flag(self.delete_credits, self.no_delete_credits),
+        );
+        set(
+            &mut settings.download_covers,
+            flag(self.download_covers, self.no_download_covers),
+        );
+        set(
+            &mut settings.keep_page_numbers,
+            flag(self.keep_page_numbers, self.no_keep_page_numbers),
+        );
+        set(
+            &mut settings.only_if_smaller,
+            flag(self.only_if_smaller, self.no_only_if_smaller),
+        );
+        set(
+            &mut settings.package_chapters,
+            flag(self.package_chapters, self.no_package_chapters),
+        );
+        set(
+            &mut settings.skip_credits,
+            flag(self.skip_credits, self.no_skip_credits),
+        );
+        set(&mut settings.verbose, flag(self.verbose, self.no_verbose));
+
+        settings
+    }
+
+    /// Prints a status message, unless stdout is used for JSON progress
+    /// or for output meant for other tools.
+    pub fn status(&self, message: impl Display) {
+        if self.progress == ProgressFormat::Bars && self.output_format == OutputFormat::Table {
+            println!("{}", message);
+        }
+    }
+
+    /// Prints the effective settings for this run as TOML.
+    ///
+    /// # Errors
+    /// Fails if the settings cannot be serialized.
+    pub fn show_config(&self, config: &Config) -> Result<()> {
+        let settings = self.settings(config, self.series_id().as_ref());
+        print!("{}", toml::to_string_pretty(&settings)?);
+        Ok(())
+    }
+
+    /// Deletes credit pages if asked to, then packages the downloaded
+    /// volumes in the formats asked for, if any.
+    ///
+    /// # Errors
+    /// Fails if a credit page could not be deleted or a package could not
+    /// be written.
+    pub fn package(
+        &self,
+        config: &Config,
+        manga: &Manga,
+        progress: &dyn ProgressSink,
+    ) -> Result<()> {
+        let settings = self.settings(config, Some(&manga.id));
+        let removed = manga.remove_credits()?;
+        if !removed.is_empty() {
+            self.status(format!("Deleted {} credit pages", removed.len()));
+        }
+        if !settings.package.is_empty() {
+            manga.package(&settings.package, progress)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the chapters of the manga whose folder has changed since they
+    /// were downloaded, printing each move as a diff of the old and new
+    /// path, then rebuilds the packages that changed. With `dry_run` only
+    /// the moves are printed.
+    ///
+    /// # Errors
+    /// Fails if the manga cannot be fetched, a chapter cannot be moved or a
+    /// package cannot be written.
+    pub async fn reorganize(&self, config: &Config, dry_run: bool) -> Result<()> {
+        let manga = self.get(config, &warnings()).await?;
+        let moves = manga.moves()?;
+        if moves.is_empty() {
+            println!("Every chapter is already in its folder");
+            return Ok(());
+        }
+        for planned in &moves {
+            let relative = |path: &Path| {
+                path.strip_prefix(&manga.path)
+                    .unwrap_or(path)
+                    .display()
+                    .to_string()
+            };
+            println!("- {}", relative(&planned.from));
+            println!("+ {}", relative(&planned.to));
+        }
+        if dry_run {
+            println!("{} chapters would be moved", moves.len());
+            return Ok(());
+        }
+
+        manga.reorganize(&moves)?;
+        println!("Moved {} chapters", moves.len());
+        let settings = self.settings(config, Some(&manga.id));
+        if !settings.package.is_empty() {
+            let packages = manga.repackage(&settings.package, &moves, &NoProgress)?;
+            println!("Rebuilt {} packages", packages.len());
+        }
+        Ok(())
+    }
+
+    /// Prints what downloading the manga would do, and saves the plan if
+    /// asked to.
+    ///
+    /// # Errors
+    /// Fails if the manga cannot be fetched or the plan cannot be saved.
+    pub async fn dry_run(&self, config: &Config) -> Result<()> {
+        let manga = self.get(config, &warnings()).await?;
+        let plan = Plan::new(&manga)?;
+        match self.output_format {
+            OutputFormat::Table => print!("{}", plan::table(&plan)),
+            OutputFormat::Json => print!("{}", output::json(&plan)?),
+            OutputFormat::Plain => print!("{}", plan::listing(&plan).plain()),
+        }
+        if let Some(path) = &self.save_plan {
+            plan.save(path)?;
+            self.status(format!("Saved the plan to {}", path.display()));
+        }
+        Ok(())
+    }
+
+    /// Searches mangadex for the title and prints the mangas found, or
+    /// prints the manga the ids given point at when there is no title.
+    ///
+    /// # Errors
+    /// Fails if there is neither a title nor an id, or the search fails.
+    pub async fn search(&self, config: &Config) -> Result<()> {
+        let downloader = self.downloader(config, &warnings())?;
+        let results = match &self.title {
+            Some(title) => {
+                downloader
+                    .search(title, self.settings(config, None).translated_language)
+                    .await?
+            }
+            // Without a title the manga is looked up by its ids instead
+            None => {
+                let id = match self.query(config, None)? {
+                    SeriesQuery::Id(id) => id,
+                    query => downloader.resolve(&query).await?,
+                };
+                vec![downloader.search_result(id).await?]
+            }
+        };
+        match self.output_format {
+            OutputFormat::Table => print!("{}", output::search(&results).table()),
+            OutputFormat::Json => print!("{}", output::json(&results)?),
+            OutputFormat::Plain => print!("{}", output::search(&results).plain()),
+        }
+        Ok(())
+    }
+
+    /// Prints the details of the manga and its selected chapters.
+    ///
+    /// # Errors
+    /// Fails if the manga cannot be fetched.
+    pub async fn info(&self, config: &Config) -> Result<()> {
+        let info = SeriesInfo::new(&self.get(config, &warnings()).await?);
+        match self.output_format {
+            OutputFormat::Table => print!("{}", output::info_table(&info)),
+            OutputFormat::Json => print!("{}", output::json(&info)?),
+            OutputFormat::Plain => print!("{}", output::chapters(&info).plain()),
+        }
+        Ok(())
+    }
+
+    /// Prints the margins cropping would take off every page below `path`.
+    ///
+    /// # Errors
+    /// Fails if a folder cannot be read or a page cannot be decoded.
+    pub fn crop_preview(&self, config: &Config, path: &Path) -> Result<()> {
+        let crop = self
+            .settings(config, self.series_id().as_ref())
+            .crop_options();
+        let pages = crop.preview(path)?;
+        let mut cropped = 0;
+        let mut total = 0;
+        for (page, margins) in &pages {
+            let pixels = u64::from(margins.width) * u64::from(margins.height);
+            cropped += margins.pixels();
+            total += pixels;
+            println!(
+                "{}: left {}, top {}, right {}, bottom {} ({:.1}%)",
+                page.strip_prefix(path).unwrap_or(page).display(),
+                margins.left,
+                margins.top,
+                margins.right,
+                margins.bottom,
+                percent(margins.pixels(), pixels)
+            );
+        }
+        println!(
+            "{} pages would lose {:.1}% of their pixels",
+            pages.len(),
+            percent(cropped, total)
+        );
+        Ok(())
+    }
+
+    /// Removes every cached api response.
+    ///
+    /// # Errors
+    /// Fails if there is no cache directory or it cannot be removed.
+    pub fn clear_cache(&self) -> Result<()> {
+        let dir = Config::cache_dir().context("No cache directory!")?;
+        Cache::new(&dir, Duration::ZERO).clear()?;
+        self.status(format!("Cleared {}", dir.display()));
+        Ok(())
+    }
+
+    /// The api response cache, unless it is turned off.
+    pub fn cache(&self, settings: &Settings) -> Option<Cache> {
+        if self.no_cache || settings.cache_ttl == 0 {
+            return None;
+        }
+        Config::cache_dir().map(|dir| Cache::new(dir, Duration::from_secs(settings.cache_ttl)))
+    }
+
+    /// Uses either the ID provided or searches mangadex for the
+    /// provided manga, then fetches its metadata and chapters and narrows
+    /// them down to the requested chapters and volumes.
+    /// If an ID is not provided a title or `anilist_id` is required. With
+    /// both we use the anilist id to validate our search result, and
+    /// search for the titles `AniList` knows if the title finds nothing.
+    /// If we don't have an anilist id we just take the first result
+    /// returned when we search mangadex. We don't do any huristics
+    /// on our side. So it is advised to use search in conjunction
+    /// with an `anilist_id` value. Responses the cache cannot save are
+    /// reported to `progress`.
+    pub async fn get(&self, config: &Config, progress: &Arc<dyn ProgressSink>) -> Result<Manga> {
+        self.fetch(config, None, progress).await
+    }
+
+    /// Fetches the manga of a plan saved with `--save-plan`, with its
+    /// chapters and folders replaced by the plan's.
+    ///
+    /// # Errors
+    /// Fails if the plan cannot be read or the manga cannot be fetched.
+    pub async fn get_planned(
+        &self,
+        config: &Config,
+        path: &Path,
+        progress: &Arc<dyn ProgressSink>,
+    ) -> Result<Manga> {
+        let plan = Plan::load(path)?;
+        let mut manga = self.fetch(config, Some(&plan), progress).await?;
+        manga.follow(&plan)?;
+        self.status(format!(
+            "Following the plan of {} chapters",
+            manga.chapters().count()
+        ));
+        Ok(manga)
+    }
+
+    /// A downloader using the cache, cassette and `AniList` api asked for.
+    fn downloader(&self, config: &Config, progress: &Arc<dyn ProgressSink>) -> Result<Downloader> {
+        let settings = self.settings(config, None);
+        let mut downloader = Downloader::builder().progress(Arc::clone(progress));
+        if let Some(cache) = self.cache(&settings) {
+            downloader = downloader.cache(cache);
+        }
+        if let Some(url) = &settings.anilist_url {
+            downloader = downloader.anilist_url(
+                url.parse()
+                    .with_context(|| format!("Invalid AniList url {:?}", url))?,
+            );
+        }
+        if let Some(path) = &self.record {
+            downloader = downloader.cassette(CassetteMode::Record(path.clone()));
+        }
+        if let Some(path) = &self.replay {
+            downloader = downloader.cassette(CassetteMode::Replay(path.clone()));
+        }
+        downloader.build()
+    }
+
+    /// How to find the manga: the plan's, the one given as an identifier or
+    /// with `--id`, or a search for `--title`.
+    fn query(&self, config: &Config, plan: Option<&Plan>) -> Result<SeriesQuery> {
+        let translated_language = self.settings(config, None).translated_language;
+        Ok(match (plan, &self.target) {
+            (Some(plan), _) => SeriesQuery::Id(plan.id),
+            (None, Some(Identifier::Series(id))) => SeriesQuery::Id(*id),
+            (None, Some(Identifier::Chapter(id))) => SeriesQuery::Chapter(*id),
+            (None, Some(Identifier::Link { site, id }))
+                if self.title.is_none() && !site.has_titles() =>
+            {
+                bail!(
+                    "{}:{} needs a --title to search for, mangadex cannot look mangas up by their {} id",
+                    site,
+                    id,
+                    site
+                )
+            }
+            (None, Some(Identifier::Link { site, id })) => SeriesQuery::Link {
+                site: *site,
+                id: id.clone(),
+                title: self.title.clone(),
+                translated_language,
+            },
+            (None, None) => match (self.id, &self.title, self.anilist_id) {
+                (Some(id), _, _) => SeriesQuery::Id(id),
+                (None, Some(title), anilist_id) => SeriesQuery::Title {
+                    title: title.clone(),
+                    anilist_id,
+                    translated_language,
+                },
+                (None, None, Some(anilist_id)) => SeriesQuery::Link {
+                    site: Site::Anilist,
+                    id: anilist_id.to_string(),
+                    title: None,
+                    translated_language,
+                },
+                (None, None, None) => bail!("Missing title!"),
+            },
+        })
+    }
+
+    async fn fetch(
+        &self,
+        config: &Config,
+        plan: Option<&Plan>,
+        progress: &Arc<dyn ProgressSink>,
+    ) -> Result<Manga> {
+        let downloader = self.downloader(config, progress)?;
+        let id: Uuid = match self.query(config, plan)? {
+            SeriesQuery::Id(id) => id,
+            query => {
+                self.status("Searching for Manga ID...");
+                let id = downloader.resolve(&query).await?;
+                self.status(format!("Found Manga ID of {}", id));
+                id
+            }
+        };
+        let settings = self.settings(config, Some(&id));
+        settings.check()?;
+        let credits = settings.credit_filter();
+        let processing = settings.processing();
+        let output = match (plan, settings.output) {
+            (Some(plan), _) => plan.path.clone(),
+            (None, Some(output)) => output,
+            // Listing chapters needs no folder, only downloading them does
+            (None, None) if matches!(self.command, Some(Command::Info)) => PathBuf::from("{title}"),
+            (None, None) => bail!("Missing Output!"),
+        };
+
+        // A chapter link only downloads that chapter
+        let chapter_id = match self.target {
+            Some(Identifier::Chapter(chapter)) if plan.is_none() => Some(chapter),
+            _ => None,
+        };
+
+        self.status("Fetching Manga...");
+        let mut manga = downloader
+            .series(id)
+            .all_volume_covers(settings.all_volume_covers)
+            .chapter(chapter_id)
+            .cover_locales(settings.cover_language)
+            .cover_policy(settings.cover_policy)
+            .cover_thumbnails(settings.cover_thumbnails)
+            .credit_filter(credits)
+            .download_covers(settings.download_covers)
+            .output(output)
+            .package_chapters(settings.package_chapters)
+            .preferred_groups(settings.preferred_groups)
+            .processing(processing)
+            .reading_direction(settings.reading_direction)
+            .title(settings.title)
+            .translated_language(settings.translated_language)
+            .volume_grouping(settings.volume_grouping)
+            .fetch()
+            .await?;
+        manga.select(&Selection {
+            chapters: self.chapters,
+            volumes: self.volumes,
+            chapter_id,
+        });
+
+        self.status(format!(
+            "Got {} chapters over {} volumes",
+            manga.chapters().count(),
+            manga.volumes.len()
+        ));
+        Ok(manga)
+    }
+}
@@ -0,0 +1,465 @@
+use anyhow::{bail, Context, Result};
+use manga_dl_core::{
+    CoverLocales, CoverPolicy, CreditFilter, Crop, Device, Language, PackageFormat, PageFormat,
+    PageHash, Processing, ReadingDirection, Spreads, Transcode, VolumeGrouping, Webtoon,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+const CONFIG_DIR: &str = "manga-dl";
+const CONFIG_FILE: &str = "config.toml";
+/// How long cached api responses are used before revalidating them.
+const DEFAULT_CACHE_TTL: u64 = 60 * 60;
+
+/// Settings loaded from the TOML config file. Every key mirrors the
+/// matching `GetManga` field, and the `[series."<uuid>"]` tables hold
+/// overrides for a single manga.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub all_volume_covers: Option<bool>,
+    /// The `AniList` `GraphQL` api used to look up mangas by their `AniList` or
+    /// `MyAnimeList` id
+    pub anilist_url: Option<String>,
+    /// Seconds api responses are cached for, 0 disables the cache
+    pub cache_ttl: Option<u64>,
+    pub cover_language: Option<CoverLocales>,
+    pub cover_policy: Option<CoverPolicy>,
+    pub cover_thumbnails: Option<bool>,
+    /// Hashes of pages that are always credits, from `manga-dl credit-hash`
+    pub credit_blocklist: Vec<PageHash>,
+    pub crop: Option<bool>,
+    pub crop_tolerance: Option<u8>,
+    /// Delete credit pages from disk as well as leaving them out of
+    /// packages
+    pub delete_credits: Option<bool>,
+    /// The e-reader to process pages for
+    pub device: Option<Device>,
+    pub download_covers: Option<bool>,
+    /// Overrides the JPEG quality of the device profile
+    pub jpeg_quality: Option<u8>,
+    pub keep_page_numbers: Option<bool>,
+    /// Only keep transcoded pages that are smaller
+    pub only_if_smaller: Option<bool>,
+    pub output: Option<PathBuf>,
+    pub package: Vec<PackageFormat>,
+    /// Package every chapter on its own instead of with its volume
+    pub package_chapters: Option<bool>,
+    pub preferred_groups: Vec<String>,
+    /// Overrides the direction taken from the manga's language and tags
+    pub reading_direction: Option<ReadingDirection>,
+    /// Leave the credit pages of scanlation groups out of packages
+    pub skip_credits: Option<bool>,
+    /// What to do with two-page spreads
+    pub spreads: Option<Spreads>,
+    /// The format to transcode pages to
+    pub transcode: Option<PageFormat>,
+    pub transcode_quality: Option<u8>,
+    pub translated_language: Option<Language>,
+    pub verbose: Option<bool>,
+    /// How chapters without a volume are grouped
+    pub volume_grouping: Option<VolumeGrouping>,
+    /// Whether to slice long strips into pages
+    pub webtoon: Option<Webtoon>,
+    pub series: HashMap<Uuid, SeriesConfig>,
+}
+
+/// Overrides that only apply to one manga.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeriesConfig {
+    pub all_volume_covers: Option<bool>,
+    pub cover_language: Option<CoverLocales>,
+    pub cover_policy: Option<CoverPolicy>,
+    pub cover_thumbnails: Option<bool>,
+    pub credit_blocklist: Vec<PageHash>,
+    pub crop: Option<bool>,
+    pub crop_tolerance: Option<u8>,
+    pub delete_credits: Option<bool>,
+    pub device: Option<Device>,
+    pub download_covers: Option<bool>,
+    pub jpeg_quality: Option<u8>,
+    pub keep_page_numbers: Option<bool>,
+    pub only_if_smaller: Option<bool>,
+    pub output: Option<PathBuf>,
+    pub package: Vec<PackageFormat>,
+    pub package_chapters: Option<bool>,
+    pub preferred_groups: Vec<String>,
+    pub reading_direction: Option<ReadingDirection>,
+    pub skip_credits: Option<bool>,
+    pub spreads: Option<Spreads>,
+    pub title: Option<String>,
+    pub transcode: Option<PageFormat>,
+    pub transcode_quality: Option<u8>,
+    pub translated_language: Option<Language>,
+    pub volume_grouping: Option<VolumeGrouping>,
+    pub webtoon: Option<Webtoon>,
+}
+
+/// The effective settings of a run once the config file, the series
+/// overrides and the command line flags have been merged.
+#[derive(Debug, Clone, Serialize)]
+pub struct Settings {
+    pub all_volume_covers: bool,
+    pub anilist_url: Option<String>,
+    pub cache_ttl: u64,
+    pub cover_language: CoverLocales,
+    pub cover_policy: CoverPolicy,
+    pub cover_thumbnails: bool,
+    pub credit_blocklist: Vec<PageHash>,
+    pub crop: bool,
+    pub crop_tolerance: u8,
+    pub delete_credits: bool,
+    pub device: Option<Device>,
+    pub download_covers: bool,
+    pub jpeg_quality: Option<u8>,
+    pub keep_page_numbers: bool,
+    pub only_if_smaller: bool,
+    pub output: Option<PathBuf>,
+    pub package: Vec<PackageFormat>,
+    pub package_chapters: bool,
+    pub preferred_groups: Vec<String>,
+    pub reading_direction: Option<ReadingDirection>,
+    pub skip_credits: bool,
+    pub spreads: Spreads,
+    pub title: Option<String>,
+    pub transcode: Option<PageFormat>,
+    pub transcode_quality: Option<u8>,
+    pub translated_language: Language,
+    pub verbose: bool,
+    pub volume_grouping: VolumeGrouping,
+    pub webtoon: Webtoon,
+}
+
+impl Config {
+    /// The default location of the config file, following the XDG base
+    /// directory spec on Linux (`~/.config/manga-dl/config.toml`).
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+
+    /// Where api responses are cached (`~/.cache/manga-dl` on Linux).
+    pub fn cache_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join(CONFIG_DIR))
+    }
+
+    /// Loads the config file at `path`, or from the default location if no
+    /// path is given. A missing default config is not an error, a missing
+    /// explicit one is.
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or is not valid TOML.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(default) if default.exists() => default,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Unable to parse config file {}", path.display()))
+    }
+
+    /// Merges the global settings with the overrides for the given series.
+    /// Command line flags are applied on top of this by the caller.
+    pub fn settings(&self, id: Option<&Uuid>) -> Settings {
+        let series = id.and_then(|id| self.series.get(id));
+
+        Settings {
+            all_volume_covers: series
+                .and_then(|series| series.all_volume_covers)
+                .or(self.all_volume_covers)
+                .unwrap_or(false),
+            anilist_url: self.anilist_url.clone(),
+            cache_ttl: self.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL),
+            cover_language: series
+                .and_then(|series| series.cover_language.clone())
+                .or_else(|| self.cover_language.clone())
+                .unwrap_or_default(),
+            cover_policy: series
+                .and_then(|series| series.cover_policy)
+                .or(self.cover_policy)
+                .unwrap_or_default(),
+            cover_thumbnails: series
+                .and_then(|series| series.cover_thumbnails)
+                .or(self.cover_thumbnails)
+                .unwrap_or(false),
+            credit_blocklist: match series {
+                Some(series) if !series.credit_blocklist.is_empty() => {
+                    series.credit_blocklist.clone()
+                }
+                _ => self.credit_blocklist.clone(),
+            },
+            crop: series
+                .and_then(|series| series.crop)
+                .or(self.crop)
+                .unwrap_or(false),
+            crop_tolerance: series
+                .and_then(|series| series.crop_tolerance)
+                .or(self.crop_tolerance)
+                .unwrap_or(Crop::default().tolerance),
+            delete_credits: series
+                .and_then(|series| series.delete_credits)
+                .or(self.delete_credits)
+                .unwrap_or(false),
+            device: series.and_then(|series| series.device).or(self.device),
+            download_covers: series
+                .and_then(|series| series.download_covers)
+                .or(self.download_covers)
+                .unwrap_or(false),
+            jpeg_quality: series
+                .and_then(|series| series.jpeg_quality)
+                .or(self.jpeg_quality),
+            keep_page_numbers: series
+                .and_then(|series| series.keep_page_numbers)
+                .or(self.keep_page_numbers)
+                .unwrap_or(false),
+            only_if_smaller: series
+                .and_then(|series| series.only_if_smaller)
+                .or(self.only_if_smaller)
+                .unwrap_or(false),
+            output: series
+                .and_then(|series| series.output.clone())
+                .or_else(|| self.output.clone()),
+            package: match series {
+                Some(series) if !series.package.is_empty() => series.package.clone(),
+                _ => self.package.clone(),
+            },
+            package_chapters: series
+                .and_then(|series| series.package_chapters)
+                .or(self.package_chapters)
+                .unwrap_or(false),
+            preferred_groups: match series {
+                Some(series) if !series.preferred_groups.is_empty() => {
+                    series.preferred_groups.clone()
+                }
+                _ => self.preferred_groups.clone(),
+            },
+            reading_direction: series
+                .and_then(|series| series.reading_direction)
+                .or(self.reading_direction),
+            skip_credits: series
+                .and_then(|series| series.skip_credits)
+                .or(self.skip_credits)
+                .unwrap_or(false),
+            spreads: series
+                .and_then(|series| series.spreads)
+                .or(self.spreads)
+                .unwrap_or_default(),
+            title: series.and_then(|series| series.title.clone()),
+            transcode: series
+                .and_then(|series| series.transcode)
+                .or(self.transcode),
+            transcode_quality: series
+                .and_then(|series| series.transcode_quality)
+                .or(self.transcode_quality),
+            translated_language: series
+                .and_then(|series| series.translated_language)
+                .or(self.translated_language)
+                .unwrap_or(Language::English),
+            verbose: self.verbose.unwrap_or(false),
+            volume_grouping: series
+                .and_then(|series| series.volume_grouping)
+                .or(self.volume_grouping)
+                .unwrap_or_default(),
+            webtoon: series
+                .and_then(|series| series.webtoon)
+                .or(self.webtoon)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Rejects settings that would only fail once the pages are
+    /// downloaded: AVIF pages cannot be decoded, so credits cannot be found
+    /// among them and PDFs and AZW3 files cannot hold them.
+    ///
+    /// # Errors
+    /// Fails if AVIF is not built in, or is transcoded to while finding
+    /// credits or packaging as PDF or AZW3.
+    pub fn check(&self) -> Result<()> {
+        let Some(format @ PageFormat::Avif) = self.transcode else {
+            return Ok(());
+        };
+        if !format.is_available() {
+            bail!("AVIF support is not built in, build manga-dl with `--features avif`");
+        }
+        if self.skip_credits || self.delete_credits {
+            bail!("Credit pages cannot be found among AVIF pages, transcode to another format");
+        }
+        if let Some(package) = self
+            .package
+            .iter()
+            .find(|package| matches!(package, PackageFormat::Pdf | PackageFormat::Azw3))
+        {
+            bail!(
+                "AVIF pages cannot be packaged as {}, transcode to jpeg or png",
+                package
+            );
+        }
+        Ok(())
+    }
+
+    /// How margins are found, whether or not cropping is turned on.
+    pub fn crop_options(&self) -> Crop {
+        Crop {
+            tolerance: self.crop_tolerance,
+            keep_page_numbers: self.keep_page_numbers,
+        }
+    }
+
+    /// Finds credit pages when they are skipped or deleted.
+    pub fn credit_filter(&self) -> Option<CreditFilter> {
+        (self.skip_credits || self.delete_credits)
+            .then(|| CreditFilter::new(self.credit_blocklist.clone(), self.delete_credits))
+    }
+
+    /// What to do to downloaded pages: slice long strips or handle spreads,
+    /// crop them if asked to, apply the device's profile with the JPEG
+    /// quality overridden if one is set, then transcode them.
+    pub fn processing(&self) -> Processing {
+        Processing {
+            webtoon: self.webtoon,
+            spreads: self.spreads,
+            crop: self.crop.then(|| self.crop_options()),
+            profile: self.device.map(|device| {
+                let mut profile = device.profile();
+                if let Some(quality) = self.jpeg_quality {
+                    profile.quality = quality;
+                }
+                profile
+            }),
+            transcode: self.transcode.map(|format| Transcode {
+                format,
+                quality: self
+                    .transcode_quality
+                    .unwrap_or_else(|| format.default_quality()),
+                only_if_smaller: self.only_if_smaller,
+            }),
+            ..Processing::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "a96676e5-8ae2-425e-b549-7f15dd34a6d8";
+
+    fn config() -> Config {
+        toml::from_str(&format!(
+            r#"
+            translated_language = "en"
+            download_covers = true
+            output = "/manga/{{title}}"
+            preferred_groups = ["Global Group"]
+            volume_grouping = "covers"
+            credit_blocklist = ["00ff00ff00ff00ff"]
+
+            [series."{ID}"]
+            title = "Komi"
+            cover_language = "en,any"
+            cover_policy = "newest"
+            crop = true
+            crop_tolerance = 40
+            spreads = "split-and-keep"
+            webtoon = "auto"
+            reading_direction = "ltr"
+            transcode = "webp"
+            only_if_smaller = true
+            device = "kobo-libra"
+            jpeg_quality = 70
+            delete_credits = true
+            volume_grouping = "10"
+            translated_language = "fr"
+            preferred_groups = ["Series Group"]
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn global_settings() {
+        let settings = config().settings(None);
+        assert_eq!(settings.translated_language, Language::English);
+        assert!(settings.download_covers);
+        assert_eq!(settings.output, Some(PathBuf::from("/manga/{title}")));
+        assert_eq!(settings.preferred_groups, vec!["Global Group"]);
+        assert_eq!(settings.title, None);
+        assert_eq!(settings.credit_filter(), None);
+        assert_eq!(settings.volume_grouping, VolumeGrouping::Covers);
+    }
+
+    #[test]
+    fn series_overrides() {
+        let id: Uuid = ID.parse().unwrap();
+        let settings = config().settings(Some(&id));
+        assert_eq!(settings.translated_language, Language::French);
+        assert!(settings.download_covers);
+        assert_eq!(settings.preferred_groups, vec!["Series Group"]);
+        assert_eq!(settings.title.as_deref(), Some("Komi"));
+        assert_eq!(settings.cover_language.to_string(), "en,any");
+        assert_eq!(settings.cover_policy, CoverPolicy::Newest);
+        let profile = settings.processing().profile.unwrap();
+        assert_eq!((profile.width, profile.height), (1264, 1680));
+        assert_eq!(profile.quality, 70);
+        assert_eq!(settings.processing().crop.unwrap().tolerance, 40);
+        assert_eq!(settings.processing().spreads, Spreads::SplitAndKeep);
+        assert_eq!(settings.processing().webtoon, Webtoon::Auto);
+        assert_eq!(
+            settings.reading_direction,
+            Some(ReadingDirection::LeftToRight)
+        );
+        let transcode = settings.processing().transcode.unwrap();
+        assert_eq!(transcode.format, PageFormat::Webp);
+        assert_eq!(transcode.quality, 80);
+        assert!(transcode.only_if_smaller);
+        let credits = settings.credit_filter().unwrap();
+        assert_eq!(credits.blocklist, vec![PageHash(0x00ff_00ff_00ff_00ff)]);
+        assert!(credits.delete);
+        assert_eq!(settings.volume_grouping, VolumeGrouping::Every(10));
+    }
+
+    #[test]
+    fn empty_config_defaults() {
+        let settings = Config::default().settings(None);
+        assert_eq!(settings.translated_language, Language::English);
+        assert!(!settings.download_covers);
+        assert_eq!(settings.output, None);
+        assert_eq!(settings.cache_ttl, DEFAULT_CACHE_TTL);
+        assert_eq!(settings.cover_language, CoverLocales::default());
+        assert_eq!(settings.cover_policy, CoverPolicy::First);
+        assert!(!settings.processing().is_enabled());
+    }
+
+    #[test]
+    fn avif_is_checked_before_downloading() {
+        let mut settings = Config::default().settings(None);
+        settings.transcode = Some(PageFormat::Avif);
+        assert_eq!(settings.check().is_ok(), PageFormat::Avif.is_available());
+        settings.package = vec![PackageFormat::Cbz, PackageFormat::Pdf];
+        assert!(settings.check().is_err());
+        settings.package.clear();
+        settings.skip_credits = true;
+        assert!(settings.check().is_err());
+        settings.transcode = Some(PageFormat::Jpeg);
+        settings.package = vec![PackageFormat::Pdf];
+        assert!(settings.check().is_ok());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("translated_langauge = \"en\"").is_err());
+    }
+}
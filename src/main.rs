@@ -37,16 +37,64 @@
     clippy::wildcard_imports
 )]
 use anyhow::Result;
-use clap::Parser;
 
-mod int_range;
-mod manga;
-use manga::get::GetManga;
+mod cli;
+mod config;
+mod output;
+mod plan;
+mod progress;
+mod shutdown;
+use cli::{CacheCommand, Command, ConfigCommand, GetManga};
+use config::Config;
+use manga_dl_core::{CancellationToken, JsonSink, ProgressSink};
+use progress::{Bars, ProgressFormat};
+use shutdown::{cancel_on_signal, EXIT_INTERRUPTED};
+use std::{path::Path, sync::Arc};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let manga = GetManga::parse();
-    manga.get().await?.download().await?;
+    let manga = GetManga::from_args()?;
+    let config = Config::load(manga.config.as_deref())?;
+
+    match &manga.command {
+        Some(Command::Config {
+            action: ConfigCommand::Show,
+        }) => manga.show_config(&config)?,
+        Some(Command::Cache {
+            action: CacheCommand::Clear,
+        }) => manga.clear_cache()?,
+        Some(Command::CropPreview { path }) => manga.crop_preview(&config, path)?,
+        Some(Command::CreditHash { pages }) => cli::credit_hash(pages)?,
+        Some(Command::Reorganize { dry_run }) => manga.reorganize(&config, *dry_run).await?,
+        Some(Command::Search) => manga.search(&config).await?,
+        Some(Command::Info) => manga.info(&config).await?,
+        Some(Command::Download { plan }) => download(&manga, &config, Some(plan.as_path())).await?,
+        None if manga.dry_run => manga.dry_run(&config).await?,
+        None => download(&manga, &config, None).await?,
+    }
 
     Ok(())
 }
+
+/// Downloads and packages the manga, or the manga of a saved plan.
+async fn download(manga: &GetManga, config: &Config, plan: Option<&Path>) -> Result<()> {
+    let sink: Arc<dyn ProgressSink> = match manga.progress {
+        ProgressFormat::Bars => Arc::new(Bars::new()?),
+        ProgressFormat::Json => Arc::new(JsonSink::new(std::io::stdout())),
+    };
+    let cancel = CancellationToken::new();
+    cancel_on_signal(cancel.clone());
+
+    let series = match plan {
+        Some(path) => manga.get_planned(config, path, &sink).await?,
+        None => manga.get(config, &sink).await?,
+    };
+    let summary = series.download(sink.as_ref(), &cancel).await?;
+    manga.status(&summary);
+    if summary.cancelled {
+        // Exiting skips destructors, the gateway may also still hold the sink
+        sink.finish();
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    manga.package(config, &series, sink.as_ref())
+}
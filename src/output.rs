@@ -0,0 +1,171 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use manga_dl_core::{info::ChapterInfo, SearchResult, SeriesInfo};
+use serde::Serialize;
+
+/// How `search`, `info` and `--dry-run` print what they found.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned columns for humans
+    #[default]
+    Table,
+    /// Pretty printed JSON
+    Json,
+    /// The rows of the table without its header, cells separated by tabs
+    Plain,
+}
+
+/// Rows of cells under a header.
+#[derive(Debug)]
+pub struct Listing {
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Listing {
+    /// The header and rows as columns padded to the widest cell.
+    pub fn table(&self) -> String {
+        let header: Vec<String> = self.header.iter().map(ToString::to_string).collect();
+        let mut widths = vec![0; header.len()];
+        for row in self.rows.iter().chain([&header]) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let mut table = String::new();
+        for row in [&header].into_iter().chain(&self.rows) {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            table.push_str(cells.join("  ").trim_end());
+            table.push('\n');
+        }
+        table
+    }
+
+    /// The rows as tab separated lines, for `cut` and the like.
+    pub fn plain(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| {
+                let cells: Vec<String> = row.iter().map(|cell| cell.replace('\t', " ")).collect();
+                format!("{}\n", cells.join("\t"))
+            })
+            .collect()
+    }
+}
+
+/// A value as pretty printed JSON, ending in a newline.
+///
+/// # Errors
+/// Fails if the value cannot be serialized.
+pub fn json(value: &impl Serialize) -> Result<String> {
+    Ok(format!("{}\n", serde_json::to_string_pretty(value)?))
+}
+
+/// A chapter number like `3` or `3.5`.
+pub fn chapter_number(chapter: u32, sub_chapter: Option<u32>) -> String {
+    sub_chapter.map_or_else(|| chapter.to_string(), |sub| format!("{}.{}", chapter, sub))
+}
+
+/// A volume number, or `-` for chapters without one.
+pub fn volume_number(volume: Option<u32>) -> String {
+    volume.map_or_else(|| "-".to_string(), |volume| volume.to_string())
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+/// One row per search result.
+pub fn search(results: &[SearchResult]) -> Listing {
+    Listing {
+        header: vec!["ID", "Year", "Status", "AniList", "Title"],
+        rows: results
+            .iter()
+            .map(|result| {
+                vec![
+                    result.id.to_string(),
+                    optional(result.year),
+                    optional(result.status.as_ref()),
+                    optional(result.links.get("al")),
+                    result.title.clone(),
+                ]
+            })
+            .collect(),
+    }
+}
+
+/// One row per chapter.
+pub fn chapters(info: &SeriesInfo) -> Listing {
+    let row = |volume: Option<u32>, chapter: &ChapterInfo| {
+        vec![
+            volume_number(volume),
+            chapter_number(chapter.chapter, chapter.sub_chapter),
+            chapter.id.to_string(),
+            chapter.pages.to_string(),
+            chapter.groups.join(", "),
+            chapter.title.clone().unwrap_or_default(),
+        ]
+    };
+    Listing {
+        header: vec!["Vol.", "Ch.", "ID", "Pages", "Groups", "Title"],
+        rows: info
+            .volumes
+            .iter()
+            .flat_map(|volume| {
+                volume
+                    .chapters
+                    .iter()
+                    .map(move |chapter| row(volume.volume, chapter))
+            })
+            .collect(),
+    }
+}
+
+/// The manga's details followed by its chapters.
+pub fn info_table(info: &SeriesInfo) -> String {
+    let links: Vec<String> = info
+        .links
+        .iter()
+        .map(|(site, id)| format!("{}:{}", site, id))
+        .collect();
+    let mut table = format!(
+        "{} ({})\nAuthors: {}\nYear: {}, status: {}, read {}\nLinks: {}\nSaved in {}\n",
+        info.title,
+        info.id,
+        info.authors.join(", "),
+        optional(info.year),
+        optional(info.status.as_ref()),
+        info.reading_direction,
+        links.join(" "),
+        info.path.display()
+    );
+    table.push_str(&chapters(info).table());
+    table.push_str(&format!(
+        "{} chapters translated into {}\n",
+        info.chapters().count(),
+        info.language
+    ));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_pad_columns_and_plain_uses_tabs() {
+        let listing = Listing {
+            header: vec!["Ch.", "Title"],
+            rows: vec![
+                vec!["1".to_string(), "Start".to_string()],
+                vec!["10.5".to_string(), "A\tB".to_string()],
+            ],
+        };
+        assert_eq!(listing.table(), "Ch.   Title\n1     Start\n10.5  A\tB\n");
+        assert_eq!(listing.plain(), "1\tStart\n10.5\tA B\n");
+    }
+}
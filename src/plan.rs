@@ -0,0 +1,101 @@
+use crate::output::{chapter_number, volume_number, Listing};
+use manga_dl_core::plan::Plan;
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_048_576.0)
+}
+
+/// One row per chapter of the plan.
+pub fn listing(plan: &Plan) -> Listing {
+    Listing {
+        header: vec!["Vol.", "Ch.", "Pages", "Size", "Groups", "Status", "Path"],
+        rows: plan
+            .chapters()
+            .map(|chapter| {
+                let path = chapter.path.as_deref().map_or_else(String::new, |path| {
+                    path.strip_prefix(&plan.path)
+                        .unwrap_or(path)
+                        .display()
+                        .to_string()
+                });
+                vec![
+                    volume_number(chapter.volume),
+                    chapter_number(chapter.chapter, chapter.sub_chapter),
+                    chapter.pages.to_string(),
+                    megabytes(chapter.estimated_bytes),
+                    chapter.groups.join(", "),
+                    if chapter.exists { "exists" } else { "new" }.to_string(),
+                    path,
+                ]
+            })
+            .collect(),
+    }
+}
+
+/// The plan as a table of chapters, with what it adds up to.
+pub fn table(plan: &Plan) -> String {
+    let mut table = format!(
+        "{} ({}), translated into {}, saved in {}\n",
+        plan.title,
+        plan.id,
+        plan.language,
+        plan.path.display()
+    );
+    table.push_str(&listing(plan).table());
+    let new = plan.chapters().filter(|chapter| !chapter.exists).count();
+    table.push_str(&format!(
+        "{} chapters to download (about {}), {} already downloaded\n",
+        new,
+        megabytes(plan.estimated_bytes()),
+        plan.chapters().count() - new
+    ));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use manga_dl_core::plan::{PlannedChapter, PlannedVolume, PAGE_BYTES};
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    #[test]
+    fn tables_list_every_chapter() {
+        let chapter = |chapter: u32, exists: bool| PlannedChapter {
+            id: Uuid::nil(),
+            chapter,
+            sub_chapter: None,
+            volume: Some(1),
+            title: None,
+            groups: vec!["Group".to_string()],
+            pages: 10,
+            estimated_bytes: 10 * PAGE_BYTES,
+            path: Some(PathBuf::from(format!("/manga/Komi/Vol. 1/Ch. {}", chapter))),
+            exists,
+        };
+        let plan = Plan {
+            id: Uuid::nil(),
+            title: "Komi".to_string(),
+            language: "en".to_string(),
+            path: PathBuf::from("/manga/Komi"),
+            volumes: vec![PlannedVolume {
+                volume: Some(1),
+                path: Some(PathBuf::from("/manga/Komi/Vol. 1")),
+                covers: 1,
+                chapters: vec![chapter(1, true), chapter(2, false)],
+            }],
+        };
+        let printed = table(&plan);
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], "Vol.  Ch.  Pages  Size    Groups  Status  Path");
+        assert_eq!(
+            lines[3],
+            "1     2    10     3.9 MB  Group   new     Vol. 1/Ch. 2"
+        );
+        assert_eq!(
+            lines[4],
+            "1 chapters to download (about 3.9 MB), 1 already downloaded"
+        );
+    }
+}
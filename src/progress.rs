@@ -0,0 +1,165 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use manga_dl_core::{ProgressEvent, ProgressSink};
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::Mutex,
+};
+
+/// How progress is reported on stdout.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// Progress bars for humans
+    #[default]
+    Bars,
+    /// One JSON object per event and line, for scripting
+    Json,
+}
+
+/// Renders the download progress as a bar for the volumes, the current
+/// volume's chapters and the current chapter's pages.
+pub struct Bars {
+    multi: MultiProgress,
+    style: ProgressStyle,
+    volumes: Mutex<Option<ProgressBar>>,
+    chapters: Mutex<Option<ProgressBar>>,
+    pages: Mutex<Option<ProgressBar>>,
+}
+
+// `ProgressStyle` has no `Debug`, so the style is left out
+impl fmt::Debug for Bars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bars")
+            .field("multi", &self.multi)
+            .field("volumes", &self.volumes)
+            .field("chapters", &self.chapters)
+            .field("pages", &self.pages)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Bars {
+    /// # Errors
+    /// Fails if the bar template is invalid.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            multi: MultiProgress::new(),
+            style: ProgressStyle::with_template(
+                "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+            )?,
+            volumes: Mutex::new(None),
+            chapters: Mutex::new(None),
+            pages: Mutex::new(None),
+        })
+    }
+
+    fn start(&self, slot: &Mutex<Option<ProgressBar>>, len: usize, message: &str) {
+        let bar = self.multi.add(
+            ProgressBar::new(len as u64)
+                .with_message(message.to_string())
+                .with_style(self.style.clone()),
+        );
+        if let Some(old) = slot.lock().unwrap().replace(bar) {
+            old.finish_and_clear();
+        }
+    }
+
+    fn inc(slot: &Mutex<Option<ProgressBar>>) {
+        if let Some(bar) = slot.lock().unwrap().as_ref() {
+            bar.inc(1);
+        }
+    }
+
+    fn clear(slot: &Mutex<Option<ProgressBar>>) {
+        if let Some(bar) = slot.lock().unwrap().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl ProgressSink for Bars {
+    fn event(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::SeriesStarted { volumes, .. } => {
+                self.start(&self.volumes, *volumes, "Downloading Volumes");
+            }
+            ProgressEvent::VolumeStarted {
+                volume, chapters, ..
+            } => {
+                if let Some(bar) = self.volumes.lock().unwrap().as_ref() {
+                    bar.set_message(format!("Vol. {:?}", volume));
+                }
+                self.start(&self.chapters, *chapters, "Downloading Chapters");
+            }
+            ProgressEvent::ChapterStarted { pages, .. } => {
+                self.start(&self.pages, *pages, "Downloading Pages");
+            }
+            ProgressEvent::PageDone { .. } => Self::inc(&self.pages),
+            ProgressEvent::ChapterDone { .. } => {
+                Self::clear(&self.pages);
+                Self::inc(&self.chapters);
+            }
+            ProgressEvent::ChapterSkipped { .. } => Self::inc(&self.chapters),
+            ProgressEvent::ChapterFailed { error, .. } => {
+                Self::clear(&self.pages);
+                let _ = self.multi.println(format!("Failed: {}", error));
+            }
+            ProgressEvent::VolumeDone { .. } => {
+                Self::clear(&self.chapters);
+                Self::inc(&self.volumes);
+            }
+            ProgressEvent::SeriesDone { cancelled, .. } => {
+                Self::clear(&self.pages);
+                Self::clear(&self.chapters);
+                if let Some(bar) = self.volumes.lock().unwrap().take() {
+                    if *cancelled {
+                        bar.abandon_with_message("Stopped");
+                    } else {
+                        bar.finish_with_message("Downloaded Volumes");
+                    }
+                }
+            }
+            ProgressEvent::SeriesCoverFailed { error, .. } => {
+                let _ = self
+                    .multi
+                    .println(format!("Failed to save the cover: {}", error));
+            }
+            ProgressEvent::PackageDone { path } => {
+                let _ = self.multi.println(format!("Packaged {}", path.display()));
+            }
+            ProgressEvent::CacheFailed { uri, error } => {
+                let _ = self
+                    .multi
+                    .println(format!("Unable to cache {}: {}", uri, error));
+            }
+            ProgressEvent::ChapterQueued { .. }
+            | ProgressEvent::SeriesCoverDone { .. }
+            | ProgressEvent::CoverDone { .. }
+            | ProgressEvent::PageFailed { .. } => {}
+        }
+    }
+
+    fn finish(&self) {
+        // Leaves the bars drawn as they are rather than clearing them
+        for slot in [&self.pages, &self.chapters, &self.volumes] {
+            if let Some(bar) = slot.lock().unwrap().take() {
+                bar.abandon();
+            }
+        }
+    }
+}
+
+/// Prints only the problems reported outside of a download, on stderr so
+/// the output on stdout stays as it is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Warnings;
+
+impl ProgressSink for Warnings {
+    fn event(&self, event: &ProgressEvent) {
+        if let ProgressEvent::CacheFailed { uri, error } = event {
+            let _ = writeln!(io::stderr(), "Unable to cache {}: {}", uri, error);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use manga_dl_core::CancellationToken;
+use std::{
+    io::{self, Write},
+    process,
+};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+#[cfg(not(unix))]
+use tokio::signal::windows::{ctrl_c, CtrlC};
+
+/// The exit code used when a run was stopped by a signal, the same code a
+/// shell reports for a process killed by SIGINT.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Cancels `cancel` on the first Ctrl-C (or SIGTERM) so the download can
+/// finish the pages in flight and save its record. A second signal exits
+/// straight away. The messages go to stderr, stdout may be JSON progress.
+pub fn cancel_on_signal(cancel: CancellationToken) {
+    tokio::spawn(async move {
+        // Without signal handling we can only be killed
+        let Ok(mut signals) = Signals::new() else {
+            return;
+        };
+        loop {
+            if signals.recv().await.is_none() {
+                return;
+            }
+            if cancel.is_cancelled() {
+                let _ = writeln!(io::stderr(), "Aborting!");
+                process::exit(EXIT_INTERRUPTED);
+            }
+            let _ = writeln!(
+                io::stderr(),
+                "Stopping after the pages in progress, press Ctrl-C again to abort."
+            );
+            cancel.cancel();
+        }
+    });
+}
+
+/// The listeners for the signals that stop a run. They are created once so
+/// no signal is missed between two waits.
+#[cfg(unix)]
+#[derive(Debug)]
+struct Signals {
+    interrupt: Signal,
+    terminate: Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> io::Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    /// Waits for the next Ctrl-C or SIGTERM, `None` once no more signals
+    /// can be received.
+    async fn recv(&mut self) -> Option<()> {
+        tokio::select! {
+            received = self.interrupt.recv() => received,
+            received = self.terminate.recv() => received,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+#[derive(Debug)]
+struct Signals(CtrlC);
+
+#[cfg(not(unix))]
+impl Signals {
+    fn new() -> io::Result<Self> {
+        Ok(Self(ctrl_c()?))
+    }
+
+    /// Waits for the next Ctrl-C, `None` once no more signals can be
+    /// received.
+    async fn recv(&mut self) -> Option<()> {
+        self.0.recv().await
+    }
+}